@@ -0,0 +1,32 @@
+use tonic_build::manual::{Builder, Method, Service};
+
+fn main() {
+    // The gRPC echo service is defined here instead of a .proto file,
+    // so building does not depend on an installed protoc
+    let echo = Service::builder()
+        .name("Echo")
+        .package("bench")
+        .method(
+            Method::builder()
+                .name("unary")
+                .route_name("Unary")
+                .input_type("super::EchoMessage")
+                .output_type("super::EchoMessage")
+                .codec_path("tonic::codec::ProstCodec")
+                .build(),
+        )
+        .method(
+            Method::builder()
+                .name("streaming")
+                .route_name("Streaming")
+                .input_type("super::EchoMessage")
+                .output_type("super::EchoMessage")
+                .codec_path("tonic::codec::ProstCodec")
+                .client_streaming()
+                .server_streaming()
+                .build(),
+        )
+        .build();
+
+    Builder::new().compile(&[echo]);
+}
@@ -0,0 +1,224 @@
+use anyhow::anyhow;
+use futures::executor::block_on;
+use futures::StreamExt;
+use lapin::options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, BasicQosOptions};
+use lapin::options::{ConfirmSelectOptions, QueuePurgeOptions};
+use lapin::publisher_confirm::{Confirmation, PublisherConfirm};
+use lapin::types::FieldTable;
+use lapin::{BasicProperties, Channel};
+use std::sync::mpsc;
+use std::time::Duration;
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, AmqpConfig};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod setup;
+
+/// Errors on a closed channel end the step, anything else only fails the message.
+fn amqp_error(channel: &Channel, e: lapin::Error, other: fn(anyhow::Error) -> BenchError) -> BenchError {
+    if channel.status().connected() {
+        return other(e.into());
+    }
+    return BenchError::connect(e);
+}
+
+struct AmqpSender {
+    channel: Channel,
+    exchange: String,
+    routing_key: String,
+    /// Confirms are awaited on another thread, so the broker round trip does not hold up sending.
+    confirms: Option<mpsc::Sender<PublisherConfirm>>,
+    properties: BasicProperties,
+}
+
+impl AmqpSender {
+    pub fn new(channel: Channel, config: &AmqpConfig) -> Self {
+        Self {
+            channel,
+            exchange: config.exchange.clone(),
+            routing_key: config.queue_send.clone(),
+            confirms: config.confirms.then(|| {
+                let (tx, rx) = mpsc::channel();
+                std::thread::spawn(move || count_confirms(rx));
+                tx
+            }),
+            properties: BasicProperties::default().with_delivery_mode(setup::delivery_mode(config.durable)),
+        }
+    }
+}
+
+impl Sender for AmqpSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let confirm = block_on(self.channel.basic_publish(
+            &self.exchange,
+            &self.routing_key,
+            BasicPublishOptions::default(),
+            &msg,
+            self.properties.clone(),
+        )).map_err(|e| amqp_error(&self.channel, e, BenchError::send))?;
+
+        // Without confirm mode the publish resolves right away as not requested
+        if let Some(confirms) = &self.confirms {
+            let _ = confirms.send(confirm);
+        }
+        return Ok(());
+    }
+}
+
+/// Awaits the publisher confirms in order until the sender is gone, rejected
+/// messages never reach the echo and count as lost.
+fn count_confirms(confirms: mpsc::Receiver<PublisherConfirm>) {
+    let (mut num_acked, mut num_nacked, mut num_failed) = (0, 0, 0);
+    for confirm in confirms {
+        match block_on(confirm) {
+            Ok(Confirmation::Nack(_)) => num_nacked += 1,
+            Ok(_) => num_acked += 1,
+            Err(e) => {
+                println!("Confirm error: {e}");
+                num_failed += 1;
+            }
+        }
+    }
+    println!("Publisher confirms: {num_acked} acked, {num_nacked} nacked, {num_failed} failed");
+}
+
+struct AmqpReceiver {
+    rx: mpsc::Receiver<lapin::Result<MsgType>>,
+}
+
+impl AmqpReceiver {
+    pub fn new(channel: &Channel, queue: &str) -> BenchResult<Self> {
+        let mut consumer = block_on(channel.basic_consume(
+            queue,
+            "amqp_bench",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )).map_err(BenchError::protocol)?;
+
+        // The consumer is a stream, so it is driven on its own thread and forwarded
+        // to a channel which supports receiving with a timeout
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || block_on(async {
+            while let Some(delivery) = consumer.next().await {
+                let result = match delivery {
+                    Ok(delivery) => delivery.ack(BasicAckOptions::default()).await.map(|_| delivery.data),
+                    Err(e) => Err(e),
+                };
+
+                if tx.send(result).is_err() {
+                    break;
+                }
+            }
+        }));
+
+        Ok(Self { rx })
+    }
+}
+
+impl Receiver for AmqpReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(Ok(msg)) => Ok(Some(msg)),
+            Ok(Err(e)) => Err(BenchError::receive(e)),
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(BenchError::connect(anyhow!("Consumer cancelled by broker")))
+            }
+        }
+    }
+}
+
+fn run_bench(config: &AmqpConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let connection = block_on(setup::connect(&config.address)).map_err(BenchError::connect)?;
+    let channel = block_on(connection.create_channel()).map_err(BenchError::connect)?;
+
+    block_on(async {
+        setup::declare_queue(&channel, &config.exchange, &config.queue_send, config.durable).await?;
+        setup::declare_queue(&channel, &config.exchange, &config.queue_recv, config.durable).await?;
+        // Echoes left over from an earlier step would be counted for this one
+        channel.queue_purge(&config.queue_recv, QueuePurgeOptions::default()).await?;
+
+        if config.confirms {
+            channel.confirm_select(ConfirmSelectOptions::default()).await?;
+        }
+        return channel.basic_qos(config.prefetch, BasicQosOptions::default()).await;
+    }).map_err(BenchError::protocol)?;
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let send = AmqpSender::new(channel.clone(), config);
+    let recv = AmqpReceiver::new(&channel, &config.queue_recv)?;
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    let result = bench.run(send, recv);
+    let _ = block_on(connection.close(200, "Step done"));
+
+    return result;
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.amqp.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_broker(Readiness::Listening(config.amqp.address.clone())).unwrap();
+        supervisor.spawn_echo(
+            "amqp-echo",
+            &[
+                &config.amqp.address,
+                &config.amqp.exchange,
+                &config.amqp.queue_send,
+                &config.amqp.queue_recv,
+                &config.amqp.prefetch.to_string(),
+                if config.amqp.durable { "durable" } else { "transient" },
+            ],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.amqp.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.amqp,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.amqp.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
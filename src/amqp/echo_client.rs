@@ -0,0 +1,95 @@
+use anyhow::Result;
+use futures::executor::block_on;
+use futures::StreamExt;
+use lapin::options::{BasicAckOptions, BasicConsumeOptions, BasicPublishOptions, BasicQosOptions};
+use lapin::types::FieldTable;
+use lapin::BasicProperties;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+mod setup;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |idx: usize, default: &str| args.get(idx).cloned().unwrap_or(default.to_string());
+
+    let addr = arg(1, "localhost:5672");
+    let exchange = arg(2, "amqp_bench");
+    let queue_send = arg(3, "amqp_send");
+    let queue_recv = arg(4, "amqp_recv");
+    let prefetch: u16 = arg(5, "100").parse()?;
+    let durable = arg(6, "transient") == "durable";
+
+    let connection = block_on(setup::connect(&addr))?;
+    let channel = block_on(connection.create_channel())?;
+    println!("Connected to broker");
+
+    let mut consumer = block_on(async {
+        setup::declare_queue(&channel, &exchange, &queue_send, durable).await?;
+        setup::declare_queue(&channel, &exchange, &queue_recv, durable).await?;
+        channel.basic_qos(prefetch, BasicQosOptions::default()).await?;
+
+        println!("Consuming queue '{queue_send}'");
+        return channel.basic_consume(
+            &queue_send,
+            "amqp_echo",
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        ).await;
+    })?;
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    // Forward the deliveries, so the loop below can notice the exit request
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || block_on(async {
+        while let Some(delivery) = consumer.next().await {
+            if tx.send(delivery).is_err() {
+                break;
+            }
+        }
+    }));
+
+    let properties = BasicProperties::default().with_delivery_mode(setup::delivery_mode(durable));
+
+    println!("Waiting for messages..");
+    while running.load(Ordering::SeqCst) {
+        let delivery = match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(Ok(delivery)) => delivery,
+            Ok(Err(e)) => {
+                println!("Delivery error: {e}");
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                println!("Consumer cancelled");
+                break;
+            }
+        };
+
+        // Only acknowledge once the echo is handed to the broker
+        let result = block_on(async {
+            channel.basic_publish(
+                &exchange,
+                &queue_recv,
+                BasicPublishOptions::default(),
+                &delivery.data,
+                properties.clone(),
+            ).await?;
+            return delivery.ack(BasicAckOptions::default()).await;
+        });
+
+        if let Err(e) = result {
+            println!("Echo error: {e}");
+        }
+    }
+
+    let _ = block_on(connection.close(200, "Shutting down"));
+    println!("Shutting down");
+    return Ok(());
+}
@@ -0,0 +1,35 @@
+use lapin::options::{ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions};
+use lapin::types::FieldTable;
+use lapin::{Channel, Connection, ConnectionProperties, ExchangeKind};
+
+pub async fn connect(addr: &str) -> lapin::Result<Connection> {
+    let uri = format!("amqp://{addr}/%2f");
+    println!("Connecting to AMQP broker at {}", uri);
+    return Connection::connect(&uri, ConnectionProperties::default()).await;
+}
+
+/// Declares the queue and binds it to the exchange with its name as routing key.
+///
+/// Declarations are idempotent, so bench and echo both declare everything they use,
+/// but they have to agree on durability or the broker closes the channel.
+pub async fn declare_queue(channel: &Channel, exchange: &str, queue: &str, durable: bool) -> lapin::Result<()> {
+    let exchange_options = ExchangeDeclareOptions {
+        durable,
+        ..Default::default()
+    };
+    channel.exchange_declare(exchange, ExchangeKind::Direct, exchange_options, FieldTable::default()).await?;
+
+    let queue_options = QueueDeclareOptions {
+        durable,
+        ..Default::default()
+    };
+    channel.queue_declare(queue, queue_options, FieldTable::default()).await?;
+    channel.queue_bind(queue, exchange, queue, QueueBindOptions::default(), FieldTable::default()).await?;
+
+    return Ok(());
+}
+
+/// Delivery mode 2 makes the broker write messages to disk.
+pub fn delivery_mode(durable: bool) -> u8 {
+    return if durable { 2 } else { 1 };
+}
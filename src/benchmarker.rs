@@ -1,59 +1,239 @@
 use anyhow::Result;
 use serde::Serialize;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::time::{Duration, Instant};
 use anyhow::anyhow;
 use hdrhistogram::Histogram;
 
+use crate::config::{DrainConfig, DrainPolicy, ScheduleConfig};
+
 pub type MsgType = Vec<u8>;
 
+/// Message number used for readiness probes, never part of the measured sequence.
+const PROBE_INDEX: usize = usize::MAX;
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Upper bound for a single `Receiver::recv` call, so the listen loop can check its cut-off.
+const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long a second Ctrl+C waits for the spawned processes before exiting.
+const CHILD_EXIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Process groups of the spawned echo servers and brokers, which don't get the terminal's Ctrl+C.
+static CHILD_GROUPS: Mutex<Vec<libc::pid_t>> = Mutex::new(Vec::new());
+
+/// Lets Ctrl+C abort the running step instead of killing the process, a second Ctrl+C exits immediately.
+///
+/// Exiting skips the supervisor's shutdown, so the spawned processes are interrupted here.
+pub fn handle_interrupt() {
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            interrupt_child_groups();
+            std::process::exit(130);
+        }
+        println!("Interrupted, aborting current step..");
+    }).expect("Error setting Ctrl-C handler");
+}
+
+pub fn is_interrupted() -> bool {
+    return INTERRUPTED.load(Ordering::SeqCst);
+}
+
+/// Interrupts the spawned processes and waits a moment for them to exit while their output is still read.
+fn interrupt_child_groups() {
+    let Ok(groups) = CHILD_GROUPS.lock() else {
+        return;
+    };
+
+    for pgid in groups.iter() {
+        unsafe { libc::kill(-pgid, libc::SIGINT) };
+    }
+
+    // The group leaders are our children, so they can be waited for by their pid
+    let mut running = groups.clone();
+    let time_start = Instant::now();
+    while !running.is_empty() && time_start.elapsed() < CHILD_EXIT_TIMEOUT {
+        running.retain(|pid| unsafe { libc::waitpid(*pid, std::ptr::null_mut(), libc::WNOHANG) } == 0);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+pub fn track_child_group(pgid: libc::pid_t) {
+    if let Ok(mut groups) = CHILD_GROUPS.lock() {
+        groups.push(pgid);
+    }
+}
+
+pub fn untrack_child_group(pgid: libc::pid_t) {
+    if let Ok(mut groups) = CHILD_GROUPS.lock() {
+        groups.retain(|group| *group != pgid);
+    }
+}
+
+/// Errors of a transport, grouped by the stage of the benchmark they happen in.
+#[derive(Debug)]
+pub enum BenchError {
+    /// Establishing or keeping the connection failed, this ends the step.
+    Connect(anyhow::Error),
+    Send(anyhow::Error),
+    Receive(anyhow::Error),
+    Timeout(anyhow::Error),
+    /// The other side answered with something unexpected.
+    Protocol(anyhow::Error),
+}
+
+impl BenchError {
+    pub fn connect(e: impl Into<anyhow::Error>) -> Self {
+        BenchError::Connect(e.into())
+    }
+
+    pub fn send(e: impl Into<anyhow::Error>) -> Self {
+        BenchError::Send(e.into())
+    }
+
+    pub fn receive(e: impl Into<anyhow::Error>) -> Self {
+        BenchError::Receive(e.into())
+    }
+
+    pub fn timeout(e: impl Into<anyhow::Error>) -> Self {
+        BenchError::Timeout(e.into())
+    }
+
+    pub fn protocol(e: impl Into<anyhow::Error>) -> Self {
+        BenchError::Protocol(e.into())
+    }
+}
+
+impl fmt::Display for BenchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BenchError::Connect(e) => write!(f, "connect error: {e:#}"),
+            BenchError::Send(e) => write!(f, "send error: {e:#}"),
+            BenchError::Receive(e) => write!(f, "receive error: {e:#}"),
+            BenchError::Timeout(e) => write!(f, "timeout: {e:#}"),
+            BenchError::Protocol(e) => write!(f, "protocol error: {e:#}"),
+        }
+    }
+}
+
+impl std::error::Error for BenchError {}
+
+pub type BenchResult<T> = std::result::Result<T, BenchError>;
+
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Completed,
+    Interrupted,
+    Failed,
+}
+
 #[derive(Debug, Serialize)]
 pub struct BenchStats {
     pub num_sent: usize,
     pub num_received: usize,
+    /// Arrived after the grace period or with a latency above `late_after_ms`.
+    pub num_late: usize,
+    pub num_lost: usize,
+    pub num_warmup: usize,
+    pub num_errors_sent: usize,
+    pub num_errors_recv: usize,
+    /// Messages which arrived after a message that was sent later.
+    pub num_reordered: usize,
     pub latency_median: u64,
     pub latency_p95: u64,
     pub latency_p99: u64,
     pub latency_std: f64,
+    pub status: RunStatus,
+    pub error: Option<String>,
 }
 
 impl BenchStats {
-    fn new(send_times: Vec<Instant>, recv_times: Vec<Option<Instant>>) -> Self {
+    /// Failed sends have no send time and count neither as sent nor as lost.
+    fn new(
+        send_times: Vec<Option<Instant>>,
+        recv_times: Vec<Option<Instant>>,
+        num_warmup: usize,
+        late_after: Option<Duration>,
+        time_cutoff: Option<Instant>,
+    ) -> Self {
 
-        let latencies: Vec<u128> = send_times.iter()
+        let (latencies, late): (Vec<_>, Vec<_>) = send_times.iter()
             .zip(recv_times)
-            .filter(|(_s, r)| r.is_some())
-            .map(|(s, r)| r.unwrap().duration_since(*s).as_micros())
-            .collect();
+            .skip(num_warmup)
+            .filter_map(|(s, r)| Some((r?.duration_since((*s)?), r?)))
+            .partition(|(latency, time_recv)| {
+                late_after.map_or(true, |late_after| *latency <= late_after)
+                    && time_cutoff.map_or(true, |time_cutoff| *time_recv <= time_cutoff)
+            });
 
         let mut hist = Histogram::<u64>::new(2).unwrap();
 
-        for time in &latencies {
-            hist += *time as u64;
+        for (time, _) in &latencies {
+            hist += time.as_micros() as u64;
         }
 
+        // An interrupted step may not even have finished the warm-up
+        let num_warmup = num_warmup.min(send_times.len());
+        let num_errors_sent = send_times[num_warmup..].iter().filter(|s| s.is_none()).count();
+        let num_sent = send_times.len() - num_warmup - num_errors_sent;
         return BenchStats {
-            num_sent: send_times.len(),
+            num_sent,
             num_received: latencies.len(),
+            num_late: late.len(),
+            num_lost: num_sent - latencies.len() - late.len(),
+            num_warmup,
+            num_errors_sent,
+            num_errors_recv: 0,
+            num_reordered: 0,
             latency_median: hist.value_at_quantile(0.5),
             latency_p95: hist.value_at_quantile(0.95),
             latency_p99: hist.value_at_quantile(0.99),
             latency_std: hist.stdev(),
+            status: RunStatus::Completed,
+            error: None,
         };
     }
+
+    /// Stats of a step which could not be measured at all.
+    pub fn failed(error: &BenchError) -> Self {
+        let mut stats = BenchStats::new(Vec::new(), Vec::new(), 0, None, None);
+        stats.status = RunStatus::Failed;
+        stats.error = Some(error.to_string());
+        return stats;
+    }
 }
 
 pub trait Sender {
-    fn send(&mut self, msg: MsgType) -> Result<()>;
+    fn send(&mut self, msg: MsgType) -> BenchResult<()>;
 }
 
 pub trait Receiver {
-    fn listen(&mut self) -> Result<Vec<Option<Instant>>>;
+    /// Waits at most `timeout` for the next echoed message.
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>>;
+
+    /// Like `recv`, with the time the message arrived, for receivers fed by another thread.
+    fn recv_timed(&mut self, timeout: Duration) -> BenchResult<Option<(MsgType, Instant)>> {
+        return Ok(self.recv(timeout)?.map(|msg| (msg, Instant::now())));
+    }
 }
 
 pub struct Benchmarker {
     pub num_messages: usize,
     pub out_file: Option<String>,
+    /// Messages are sent for this long before the measurement starts and are not part of the stats.
+    pub warmup: Duration,
+    /// How long to wait for an echoed probe before giving up, `None` skips the probe.
+    pub probe_timeout: Option<Duration>,
+    pub drain: DrainConfig,
     time_wait: Duration,
+    duration: Duration,
     message_size: usize,
 }
 
@@ -63,34 +243,247 @@ impl Benchmarker {
             time_wait: Duration::from_secs_f64(duration.as_secs_f64() / num_messages as f64),
             num_messages,
             out_file: None,
+            warmup: Duration::ZERO,
+            probe_timeout: None,
+            drain: DrainConfig::default(),
+            duration,
             message_size,
         }
     }
 
-    pub fn run(&mut self, mut sender: impl Sender, mut receiver: impl Receiver + Send + 'static) -> BenchStats {
+    /// Takes the warm-up, readiness probe and drain settings from the schedule, a probe timeout of 0 disables the probe.
+    pub fn apply_schedule(&mut self, schedule: &ScheduleConfig) {
+        self.warmup = Duration::from_secs_f64(schedule.warmup_secs);
+        self.probe_timeout = Some(Duration::from_secs_f64(schedule.probe_timeout_secs))
+            .filter(|timeout| !timeout.is_zero());
+        self.drain = schedule.drain.clone();
+    }
+
+    /// Runs one step of the schedule.
+    ///
+    /// Errors are only returned if nothing could be measured, a connection lost during the step
+    /// ends it early with a failed status instead.
+    pub fn run(&mut self, mut sender: impl Sender, mut receiver: impl Receiver + Send + 'static) -> BenchResult<BenchStats> {
+        self.wait_ready(&mut sender, &mut receiver)?;
+
+        let num_warmup = (self.warmup.as_secs_f64() / self.time_wait.as_secs_f64()).floor() as usize;
+        let num_total = num_warmup + self.num_messages;
+        let send_duration = self.warmup + self.duration;
+        let drain = self.drain.clone();
+
         let listen_handle = std::thread::spawn(move || {
-            let recv_times = receiver.listen();
+            let received = listen(&mut receiver, num_total, send_duration, &drain);
             drop(receiver);
-            return recv_times;
+            return received;
         });
 
-        let mut send_times = Vec::with_capacity(self.num_messages);
-        for msg_nr in 0..self.num_messages {
+        let mut send_times = Vec::with_capacity(num_total);
+        let mut send_error = None;
+        for msg_nr in 0..num_total {
+            if is_interrupted() {
+                break;
+            }
+
             let msg = create_message(msg_nr, self.message_size);
 
             let time_send = Instant::now();
-            send_times.push(time_send);
-            let _ = sender.send(msg);
+            match sender.send(msg) {
+                Ok(()) => send_times.push(Some(time_send)),
+                Err(BenchError::Connect(e)) => {
+                    send_error = Some(BenchError::Connect(e));
+                    break;
+                }
+                Err(_) => send_times.push(None),
+            }
 
             std::thread::sleep(self.time_wait);
         }
 
         drop(sender);
 
-        let recv_times = listen_handle.join().unwrap().unwrap();
+        let received = listen_handle.join()
+            .map_err(|_| BenchError::receive(anyhow!("Listener thread panicked")))?;
+
+        let late_after = self.drain.late_after_ms.map(Duration::from_millis);
+        let mut stats = BenchStats::new(send_times, received.timestamps, num_warmup, late_after, Some(received.time_cutoff));
+        stats.num_errors_recv = received.num_errors;
+        stats.num_reordered = received.num_reordered;
 
-        return BenchStats::new(send_times, recv_times);
+        if let Some(e) = send_error.or(received.error) {
+            stats.status = RunStatus::Failed;
+            stats.error = Some(e.to_string());
+        } else if is_interrupted() {
+            stats.status = RunStatus::Interrupted;
+        }
+
+        write_stats(&self.out_file, &stats);
+        return Ok(stats);
     }
+
+
+    /// Sends probe messages until one of them is echoed back.
+    fn wait_ready(&self, sender: &mut impl Sender, receiver: &mut impl Receiver) -> BenchResult<()> {
+        let Some(timeout) = self.probe_timeout else {
+            return Ok(());
+        };
+
+        let time_start = Instant::now();
+        let mut last_error = None;
+        while time_start.elapsed() < timeout {
+            if is_interrupted() {
+                return Err(BenchError::timeout(anyhow!("Interrupted while waiting for the echo")));
+            }
+
+            // The other side might not be up yet, so failed sends are expected here
+            let _ = sender.send(create_message(PROBE_INDEX, self.message_size));
+
+            let time_probe = Instant::now();
+            while time_probe.elapsed() < PROBE_INTERVAL {
+                // Receiving fails as well until the other side is up, the next probe tries again
+                let msg = match receiver.recv(PROBE_INTERVAL) {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        last_error = Some(e);
+                        std::thread::sleep(PROBE_INTERVAL.saturating_sub(time_probe.elapsed()));
+                        break;
+                    }
+                };
+
+                if index_from_message(msg).is_ok_and(|idx| idx == PROBE_INDEX) {
+                    return Ok(());
+                }
+            }
+        }
+
+        return match last_error {
+            Some(e) => Err(BenchError::timeout(anyhow!("No probe echoed back within {:?}, last error: {e}", timeout))),
+            None => Err(BenchError::timeout(anyhow!("No probe echoed back within {:?}", timeout))),
+        };
+    }
+}
+
+/// Records a step which failed before anything could be measured, so the schedule can go on.
+pub fn record_failure(out_file: &Option<String>, error: &BenchError) -> BenchStats {
+    let stats = BenchStats::failed(error);
+    write_stats(out_file, &stats);
+    return stats;
+}
+
+/// Appends the stats as one JSON line to the result file.
+fn write_stats(out_file: &Option<String>, stats: &BenchStats) {
+    let Some(path) = out_file else {
+        return;
+    };
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut file| Ok(writeln!(file, "{}", serde_json::to_string(stats)?)?));
+
+    if let Err(e) = result {
+        println!("Error writing results: {e}");
+    }
+}
+
+struct Received {
+    timestamps: Vec<Option<Instant>>,
+    num_errors: usize,
+    num_reordered: usize,
+    /// End of the grace period, later arrivals are late.
+    time_cutoff: Instant,
+    /// The error that ended listening early.
+    error: Option<BenchError>,
+}
+
+/// Collects the receive times until the drain policy ends the step.
+///
+/// Listening never lasts longer than the grace period after `send_duration` plus the late window,
+/// which is only waited for while messages are missing.
+fn listen(
+    receiver: &mut impl Receiver,
+    num_messages: usize,
+    send_duration: Duration,
+    drain: &DrainConfig,
+) -> Received {
+    let mut timestamps = vec![None; num_messages];
+    let mut num_received = 0;
+    let mut num_errors = 0;
+    let mut num_reordered = 0;
+    let mut idx_highest = None;
+    let mut error = None;
+
+    let cutoff = send_duration + Duration::from_secs_f64(drain.grace_secs);
+    let late_cutoff = cutoff + Duration::from_secs_f64(drain.late_window_secs);
+    let idle_timeout = Duration::from_millis(drain.idle_ms);
+
+    let time_start = Instant::now();
+    let mut time_last_msg = time_start;
+    loop {
+        if is_interrupted() {
+            break;
+        }
+
+        let elapsed = time_start.elapsed();
+        let is_late = elapsed > cutoff;
+        if num_received >= num_messages && (drain.policy != DrainPolicy::Fixed || is_late) {
+            break;
+        }
+
+        if elapsed > late_cutoff {
+            break;
+        }
+
+        let is_drained = elapsed > send_duration && time_last_msg.elapsed() > idle_timeout;
+        if drain.policy == DrainPolicy::Idle && is_drained {
+            break;
+        }
+
+        let (msg, time_recv) = match receiver.recv_timed(RECV_TIMEOUT.min(late_cutoff - elapsed)) {
+            Ok(Some(received)) => received,
+            Ok(None) => continue,
+            Err(BenchError::Connect(e)) => {
+                error = Some(BenchError::Connect(e));
+                break;
+            }
+            Err(e) => {
+                println!("Receive error: {e}");
+                num_errors += 1;
+                continue;
+            }
+        };
+        time_last_msg = time_recv;
+
+        // Late probes and foreign messages fall outside the expected range
+        let Ok(idx) = index_from_message(msg) else {
+            continue;
+        };
+        let Some(timestamp) = timestamps.get_mut(idx) else {
+            continue;
+        };
+
+        // Duplicates, e.g. from retransmissions, keep the first arrival
+        if timestamp.is_some() {
+            continue;
+        }
+        num_received += 1;
+        *timestamp = Some(time_recv);
+
+        if idx_highest.is_some_and(|highest| idx < highest) {
+            num_reordered += 1;
+        }
+        idx_highest = idx_highest.max(Some(idx));
+    }
+
+    return Received {
+        timestamps,
+        num_errors,
+        num_reordered,
+        time_cutoff: time_start + cutoff,
+        error,
+    };
 }
 
 fn create_message(msg_nr: usize, length: usize) -> MsgType {
@@ -112,4 +505,3 @@ pub fn index_from_message(msg: MsgType) -> Result<usize> {
     let idx: [u8; 8] = msg[..8].try_into()?;
     return Ok(usize::from_ne_bytes(idx));
 }
-
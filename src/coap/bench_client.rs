@@ -0,0 +1,351 @@
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure, index_from_message};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, CoapConfig, CoapMode};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod message;
+use message::{Message, MessageType, encode_uint, OPTION_OBSERVE, CODE_EMPTY, CODE_GET, CODE_POST, CODE_PUT};
+
+const URI_ECHO: &str = "echo";
+const URI_OBSERVED: &str = "obs";
+// Shorter than the message number tokens, so it never collides with them
+const OBSERVE_TOKEN: &[u8] = b"observe";
+const MAX_DATAGRAM: usize = 65535;
+
+/// A confirmable request which was not acknowledged yet.
+struct Pending {
+    /// Acknowledgements and resets only carry the message id, the token tells which message it was.
+    token: Vec<u8>,
+    data: Vec<u8>,
+    time_sent: Instant,
+    retransmits: u32,
+}
+
+/// Message ids and the requests waiting for their acknowledgement, shared by sender and receiver.
+#[derive(Default)]
+struct Exchanges {
+    next_message_id: u16,
+    pending: HashMap<u16, Pending>,
+}
+
+impl Exchanges {
+    /// Message ids wrap around, the ones of unacknowledged requests are skipped.
+    fn message_id(&mut self) -> Option<u16> {
+        if self.pending.len() > u16::MAX as usize {
+            return None;
+        }
+        while self.pending.contains_key(&self.next_message_id) {
+            self.next_message_id = self.next_message_id.wrapping_add(1);
+        }
+
+        let message_id = self.next_message_id;
+        self.next_message_id = message_id.wrapping_add(1);
+        return Some(message_id);
+    }
+}
+
+type SharedExchanges = Arc<Mutex<Exchanges>>;
+
+struct CoapSender {
+    socket: UdpSocket,
+    confirmable: bool,
+    mode: CoapMode,
+    exchanges: SharedExchanges,
+}
+
+impl CoapSender {
+    pub fn new(socket: UdpSocket, config: &CoapConfig, exchanges: SharedExchanges) -> Self {
+        Self {
+            socket,
+            confirmable: config.confirmable,
+            mode: config.mode,
+            exchanges,
+        }
+    }
+}
+
+impl Sender for CoapSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        // The message number is used as token, so responses can be matched to the request
+        let token = msg.get(..8).ok_or(BenchError::protocol(anyhow!("Message too short")))?.to_vec();
+        let mtype = match self.confirmable {
+            true => MessageType::Confirmable,
+            false => MessageType::NonConfirmable,
+        };
+
+        // In observe mode the message updates the resource and comes back as notification
+        let (code, uri_path) = match self.mode {
+            CoapMode::Request => (CODE_POST, URI_ECHO),
+            CoapMode::Observe => (CODE_PUT, URI_OBSERVED),
+        };
+
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let message_id = exchanges.message_id()
+            .ok_or(BenchError::send(anyhow!("All message ids wait for an acknowledgement")))?;
+
+        let mut request = Message::new(mtype, code, message_id, token.clone());
+        request.set_uri_path(uri_path);
+        request.payload = msg;
+        let data = request.encode();
+
+        if self.confirmable {
+            exchanges.pending.insert(message_id, Pending {
+                token,
+                data: data.clone(),
+                time_sent: Instant::now(),
+                retransmits: 0,
+            });
+        }
+        drop(exchanges);
+
+        return self.socket.send(&data).map(|_| ()).map_err(BenchError::send);
+    }
+}
+
+/// Time to wait for the acknowledgement after `retransmits` retransmissions, doubling each time.
+fn backoff(ack_timeout: Duration, retransmits: u32) -> Duration {
+    return ack_timeout.saturating_mul(2u32.saturating_pow(retransmits));
+}
+
+struct CoapReceiver {
+    socket: UdpSocket,
+    exchanges: SharedExchanges,
+    ack_timeout: Duration,
+    max_retransmit: u32,
+    observing: bool,
+    buffer: Vec<u8>,
+}
+
+impl CoapReceiver {
+    pub fn new(socket: UdpSocket, exchanges: SharedExchanges, config: &CoapConfig) -> Self {
+        Self {
+            socket,
+            exchanges,
+            ack_timeout: Duration::from_millis(config.ack_timeout_ms),
+            max_retransmit: config.max_retransmit,
+            observing: false,
+            buffer: vec![0; MAX_DATAGRAM],
+        }
+    }
+
+    /// Registers as observer of the updated resource and waits for the first response.
+    pub fn observe(&mut self, timeout: Duration) -> BenchResult<()> {
+        let time_start = Instant::now();
+        while time_start.elapsed() < timeout && !is_interrupted() {
+            let message_id = self.exchanges.lock().unwrap().message_id().unwrap_or_default();
+            let mut request = Message::new(MessageType::NonConfirmable, CODE_GET, message_id, OBSERVE_TOKEN.to_vec());
+            request.set_uri_path(URI_OBSERVED);
+            request.add_option(OPTION_OBSERVE, encode_uint(0));
+
+            // The echo server might not be up yet, so failed sends are expected here
+            let _ = self.socket.send(&request.encode());
+
+            let time_request = Instant::now();
+            while time_request.elapsed() < Duration::from_millis(500) {
+                let Some(response) = self.recv_message(Duration::from_millis(100))? else {
+                    continue;
+                };
+
+                if response.token == OBSERVE_TOKEN && response.observe().is_some() {
+                    self.observing = true;
+                    return Ok(());
+                }
+            }
+        }
+
+        return Err(BenchError::timeout(anyhow!("Observation not accepted within {timeout:?}")));
+    }
+
+    fn recv_message(&mut self, timeout: Duration) -> BenchResult<Option<Message>> {
+        let timeout = timeout.max(Duration::from_millis(1));
+        self.socket.set_read_timeout(Some(timeout)).map_err(BenchError::receive)?;
+
+        let len = match self.socket.recv(&mut self.buffer) {
+            Ok(len) => len,
+            // The echo server is not (yet) listening, which the probe takes care of
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => return Ok(None),
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                return Ok(None);
+            }
+            Err(e) => return Err(BenchError::receive(e)),
+        };
+
+        return Message::decode(&self.buffer[..len]).map(Some).map_err(BenchError::protocol);
+    }
+
+    /// Resends unacknowledged requests with exponential back-off and gives up after
+    /// `max_retransmit` attempts, in which case the message counts as lost.
+    fn retransmit(&mut self) -> BenchResult<()> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let pending = &mut exchanges.pending;
+        let max_retransmit = self.max_retransmit;
+        let ack_timeout = self.ack_timeout;
+        pending.retain(|_, request| request.retransmits < max_retransmit
+            || request.time_sent.elapsed() < backoff(ack_timeout, request.retransmits));
+
+        for request in pending.values_mut() {
+            if request.time_sent.elapsed() < backoff(ack_timeout, request.retransmits) {
+                continue;
+            }
+
+            self.socket.send(&request.data).map_err(BenchError::send)?;
+            request.time_sent = Instant::now();
+            request.retransmits += 1;
+        }
+
+        return Ok(());
+    }
+
+    fn handle(&mut self, response: Message) -> BenchResult<Option<MsgType>> {
+        let mut acknowledged = None;
+        match response.mtype {
+            MessageType::Acknowledgement | MessageType::Reset => {
+                acknowledged = self.exchanges.lock().unwrap().pending.remove(&response.message_id);
+            }
+            MessageType::Confirmable => {
+                // Separate responses have to be acknowledged by the client
+                let ack = Message::empty(MessageType::Acknowledgement, response.message_id);
+                self.socket.send(&ack.encode()).map_err(BenchError::send)?;
+            }
+            MessageType::NonConfirmable => {}
+        }
+
+        if response.mtype == MessageType::Reset {
+            return Err(BenchError::protocol(match acknowledged.and_then(|request| index_from_message(request.token).ok()) {
+                Some(idx) => anyhow!("Message {idx} reset by server"),
+                None => anyhow!("Request {} reset by server", response.message_id),
+            }));
+        }
+
+        if response.code == CODE_EMPTY {
+            // Only an acknowledgement, the response follows separately
+            return Ok(None);
+        }
+
+        // While observing only the notifications carry the messages, the responses
+        // to the updates are just confirmations
+        if self.observing {
+            if response.token != OBSERVE_TOKEN || response.payload.is_empty() {
+                return Ok(None);
+            }
+            return Ok(Some(response.payload));
+        }
+
+        if response.payload.len() < 8 || response.token != response.payload[..8] {
+            return Err(BenchError::protocol(anyhow!("Response token does not match the payload")));
+        }
+
+        return Ok(Some(response.payload));
+    }
+}
+
+impl Receiver for CoapReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        self.retransmit()?;
+
+        return match self.recv_message(timeout)? {
+            Some(response) => self.handle(response),
+            None => Ok(None),
+        };
+    }
+}
+
+impl Drop for CoapReceiver {
+    fn drop(&mut self) {
+        if !self.observing {
+            return;
+        }
+
+        let message_id = self.exchanges.lock().unwrap().message_id().unwrap_or_default();
+        let mut request = Message::new(MessageType::NonConfirmable, CODE_GET, message_id, OBSERVE_TOKEN.to_vec());
+        request.set_uri_path(URI_OBSERVED);
+        request.add_option(OPTION_OBSERVE, encode_uint(1));
+        let _ = self.socket.send(&request.encode());
+    }
+}
+
+fn run_bench(config: &CoapConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(BenchError::connect)?;
+    socket.connect(&config.address).map_err(BenchError::connect)?;
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let exchanges = SharedExchanges::default();
+    let send = CoapSender::new(
+        socket.try_clone().map_err(BenchError::connect)?,
+        config,
+        exchanges.clone(),
+    );
+    let mut recv = CoapReceiver::new(socket, exchanges, config);
+    if config.mode == CoapMode::Observe {
+        recv.observe(Duration::from_secs_f64(config.schedule.probe_timeout_secs.max(1.)))?;
+    }
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    return bench.run(send, recv);
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.coap.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "coap-echo",
+            &[&config.coap.address],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.coap.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.coap,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.coap.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
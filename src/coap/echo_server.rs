@@ -0,0 +1,230 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod message;
+use message::{Message, MessageType, encode_uint, OPTION_OBSERVE};
+use message::{CODE_CHANGED, CODE_CONTENT, CODE_EMPTY, CODE_GET, CODE_METHOD_NOT_ALLOWED, CODE_NOT_FOUND, CODE_POST, CODE_PUT};
+
+/// How long retransmissions of a confirmable request may arrive, MAX_TRANSMIT_SPAN of RFC 7252.
+const MAX_TRANSMIT_SPAN: Duration = Duration::from_secs(45);
+
+/// Peer, message id and token of a confirmable request. The token guards against
+/// clients reusing message ids faster than RFC 7252 allows.
+type ExchangeKey = (SocketAddr, u16, Vec<u8>);
+
+/// The acknowledgements of recent confirmable requests, sent again when a request
+/// is retransmitted instead of executing it twice (RFC 7252, section 4.5).
+#[derive(Default)]
+struct RecentAcks {
+    acks: HashMap<ExchangeKey, Vec<u8>>,
+    expiry: VecDeque<(Instant, ExchangeKey)>,
+}
+
+impl RecentAcks {
+    fn get(&mut self, key: &ExchangeKey) -> Option<&Vec<u8>> {
+        while let Some((time_acked, _)) = self.expiry.front() {
+            if time_acked.elapsed() < MAX_TRANSMIT_SPAN {
+                break;
+            }
+            let (_, key) = self.expiry.pop_front().unwrap();
+            self.acks.remove(&key);
+        }
+        return self.acks.get(key);
+    }
+
+    fn insert(&mut self, key: ExchangeKey, ack: Vec<u8>) {
+        if self.acks.insert(key.clone(), ack).is_none() {
+            self.expiry.push_back((Instant::now(), key));
+        }
+    }
+}
+
+struct Observer {
+    addr: SocketAddr,
+    token: Vec<u8>,
+    last_message_id: u16,
+}
+
+/// The resources served by the echo server: `echo` returns the request payload,
+/// `obs` stores the last update and notifies its observers about changes.
+struct Resources {
+    observed: Vec<u8>,
+    observers: Vec<Observer>,
+    observe_seq: u32,
+    message_id: u16,
+}
+
+impl Resources {
+    fn new() -> Self {
+        Self {
+            observed: Vec::new(),
+            observers: Vec::new(),
+            observe_seq: 0,
+            message_id: 0,
+        }
+    }
+
+    fn next_message_id(&mut self) -> u16 {
+        self.message_id = self.message_id.wrapping_add(1);
+        return self.message_id;
+    }
+
+    /// Builds the response of the given code, piggybacked for confirmable requests.
+    fn response(&mut self, request: &Message, code: u8) -> Message {
+        return match request.mtype {
+            MessageType::Confirmable => {
+                Message::new(MessageType::Acknowledgement, code, request.message_id, request.token.clone())
+            }
+            _ => {
+                let message_id = self.next_message_id();
+                Message::new(MessageType::NonConfirmable, code, message_id, request.token.clone())
+            }
+        };
+    }
+
+    fn handle(&mut self, request: &Message, peer: SocketAddr) -> Vec<(Message, SocketAddr)> {
+        if !request.is_request() {
+            // A reset to a notification cancels the observation
+            if request.mtype == MessageType::Reset {
+                self.observers.retain(|o| o.addr != peer || o.last_message_id != request.message_id);
+            }
+
+            // Empty pings are answered with a reset, acknowledgements need no answer
+            if request.code == CODE_EMPTY && request.mtype == MessageType::Confirmable {
+                return vec![(Message::empty(MessageType::Reset, request.message_id), peer)];
+            }
+            return Vec::new();
+        }
+
+        let mut responses = Vec::new();
+        match (request.uri_path().as_str(), request.code) {
+            ("echo", CODE_POST | CODE_PUT) => {
+                let mut response = self.response(request, CODE_CONTENT);
+                response.payload = request.payload.clone();
+                responses.push((response, peer));
+            }
+            ("obs", CODE_GET) => {
+                let mut response = self.response(request, CODE_CONTENT);
+                self.observers.retain(|o| o.addr != peer || o.token != request.token);
+                // Observe 0 registers, anything else deregisters or is a plain read
+                if request.observe() == Some(0) {
+                    self.observers.push(Observer {
+                        addr: peer,
+                        token: request.token.clone(),
+                        last_message_id: response.message_id,
+                    });
+                    response.add_option(OPTION_OBSERVE, encode_uint(self.observe_seq));
+                }
+                response.payload = self.observed.clone();
+                responses.push((response, peer));
+            }
+            ("obs", CODE_POST | CODE_PUT) => {
+                self.observed = request.payload.clone();
+                let response = self.response(request, CODE_CHANGED);
+                responses.push((response, peer));
+                responses.extend(self.notify());
+            }
+            ("echo" | "obs", _) => {
+                responses.push((self.response(request, CODE_METHOD_NOT_ALLOWED), peer));
+            }
+            _ => {
+                responses.push((self.response(request, CODE_NOT_FOUND), peer));
+            }
+        }
+
+        return responses;
+    }
+
+    /// Notifications are sent non-confirmable, lost ones are not repeated.
+    fn notify(&mut self) -> Vec<(Message, SocketAddr)> {
+        // The sequence number only has 24 bits on the wire
+        self.observe_seq = (self.observe_seq + 1) & 0xff_ffff;
+
+        let mut notifications = Vec::new();
+        for i in 0..self.observers.len() {
+            let message_id = self.next_message_id();
+            let observer = &mut self.observers[i];
+            observer.last_message_id = message_id;
+
+            let mut notification = Message::new(
+                MessageType::NonConfirmable,
+                CODE_CONTENT,
+                message_id,
+                observer.token.clone(),
+            );
+            notification.add_option(OPTION_OBSERVE, encode_uint(self.observe_seq));
+            notification.payload = self.observed.clone();
+            notifications.push((notification, observer.addr));
+        }
+
+        return notifications;
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let addr_default = "127.0.0.1:5683".to_string();
+    let addr = args.get(1).unwrap_or(&addr_default).to_string();
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let socket = UdpSocket::bind(&addr).unwrap();
+    // Time out regularly to notice the exit request
+    socket.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+
+    println!("Waiting for messages..");
+    let mut resources = Resources::new();
+    let mut recent_acks = RecentAcks::default();
+    let mut buffer = vec![0u8; 65535];
+    while running.load(Ordering::SeqCst) {
+        let (len, peer) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+            Err(e) => {
+                println!("Receive error: {e}");
+                continue;
+            }
+        };
+
+        let request = match Message::decode(&buffer[..len]) {
+            Ok(request) => request,
+            Err(e) => {
+                println!("Invalid message from {peer}: {e}");
+                continue;
+            }
+        };
+
+        let is_confirmable = request.mtype == MessageType::Confirmable && request.is_request();
+        let key = (peer, request.message_id, request.token.clone());
+        if is_confirmable {
+            if let Some(ack) = recent_acks.get(&key) {
+                if let Err(e) = socket.send_to(ack, peer) {
+                    println!("Send error: {e}");
+                }
+                continue;
+            }
+        }
+
+        for (response, addr) in resources.handle(&request, peer) {
+            let data = response.encode();
+            if is_confirmable && response.mtype == MessageType::Acknowledgement {
+                recent_acks.insert(key.clone(), data.clone());
+            }
+            if let Err(e) = socket.send_to(&data, addr) {
+                println!("Send error: {e}");
+            }
+        }
+    }
+
+    println!("Shutting down");
+}
@@ -0,0 +1,324 @@
+use anyhow::{anyhow, Result};
+
+pub const CODE_EMPTY: u8 = 0x00;
+pub const CODE_GET: u8 = 0x01;
+pub const CODE_POST: u8 = 0x02;
+pub const CODE_PUT: u8 = 0x03;
+pub const CODE_DELETE: u8 = 0x04;
+pub const CODE_CREATED: u8 = 0x41;
+pub const CODE_DELETED: u8 = 0x42;
+pub const CODE_CHANGED: u8 = 0x44;
+pub const CODE_CONTENT: u8 = 0x45;
+pub const CODE_BAD_REQUEST: u8 = 0x80;
+pub const CODE_NOT_FOUND: u8 = 0x84;
+pub const CODE_METHOD_NOT_ALLOWED: u8 = 0x85;
+
+pub const OPTION_OBSERVE: u16 = 6;
+pub const OPTION_LOCATION_PATH: u16 = 8;
+pub const OPTION_URI_PATH: u16 = 11;
+pub const OPTION_CONTENT_FORMAT: u16 = 12;
+pub const OPTION_URI_QUERY: u16 = 15;
+
+const PAYLOAD_MARKER: u8 = 0xff;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageType {
+    Confirmable,
+    NonConfirmable,
+    Acknowledgement,
+    Reset,
+}
+
+/// A CoAP message as defined in RFC 7252, with just enough options for the benchmarks.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub mtype: MessageType,
+    pub code: u8,
+    pub message_id: u16,
+    pub token: Vec<u8>,
+    /// Option numbers with their values, kept sorted by number.
+    pub options: Vec<(u16, Vec<u8>)>,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    pub fn new(mtype: MessageType, code: u8, message_id: u16, token: Vec<u8>) -> Self {
+        Self {
+            mtype,
+            code,
+            message_id,
+            token,
+            options: Vec::new(),
+            payload: Vec::new(),
+        }
+    }
+
+    /// An empty acknowledgement or reset for the given message.
+    pub fn empty(mtype: MessageType, message_id: u16) -> Self {
+        Self::new(mtype, CODE_EMPTY, message_id, Vec::new())
+    }
+
+    pub fn is_request(&self) -> bool {
+        return (1..32).contains(&self.code);
+    }
+
+    pub fn add_option(&mut self, number: u16, value: Vec<u8>) {
+        let idx = self.options.partition_point(|(n, _)| *n <= number);
+        self.options.insert(idx, (number, value));
+    }
+
+    pub fn option(&self, number: u16) -> Option<&[u8]> {
+        return self.options.iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, value)| value.as_slice());
+    }
+
+    pub fn set_uri_path(&mut self, path: &str) {
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            self.add_option(OPTION_URI_PATH, segment.as_bytes().to_vec());
+        }
+    }
+
+    pub fn uri_path(&self) -> String {
+        return self.options.iter()
+            .filter(|(n, _)| *n == OPTION_URI_PATH)
+            .map(|(_, value)| String::from_utf8_lossy(value).into_owned())
+            .collect::<Vec<String>>()
+            .join("/");
+    }
+
+    pub fn location_path(&self) -> String {
+        return self.options.iter()
+            .filter(|(n, _)| *n == OPTION_LOCATION_PATH)
+            .map(|(_, value)| String::from_utf8_lossy(value).into_owned())
+            .collect::<Vec<String>>()
+            .join("/");
+    }
+
+    /// The value of a `name=value` query parameter.
+    pub fn uri_query(&self, name: &str) -> Option<String> {
+        return self.options.iter()
+            .filter(|(n, _)| *n == OPTION_URI_QUERY)
+            .filter_map(|(_, value)| {
+                let query = String::from_utf8_lossy(value);
+                let (key, value) = query.split_once('=')?;
+                (key == name).then(|| value.to_string())
+            })
+            .next();
+    }
+
+    pub fn observe(&self) -> Option<u32> {
+        return self.option(OPTION_OBSERVE).map(decode_uint);
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mtype = match self.mtype {
+            MessageType::Confirmable => 0,
+            MessageType::NonConfirmable => 1,
+            MessageType::Acknowledgement => 2,
+            MessageType::Reset => 3,
+        };
+
+        assert!(self.token.len() <= 8, "Token longer than 8 bytes");
+        let mut buf = Vec::with_capacity(4 + self.token.len() + self.payload.len() + 16);
+        buf.push(1 << 6 | mtype << 4 | self.token.len() as u8);
+        buf.push(self.code);
+        buf.extend_from_slice(&self.message_id.to_be_bytes());
+        buf.extend_from_slice(&self.token);
+
+        let mut last = 0;
+        for (number, value) in &self.options {
+            // Options are encoded as deltas, `add_option` keeps them sorted
+            let delta = number.checked_sub(last).expect("Options not sorted by number");
+            let length = u16::try_from(value.len()).expect("Option value longer than 65535 bytes");
+            let (delta, delta_ext) = encode_option_nibble(delta);
+            let (length, length_ext) = encode_option_nibble(length);
+            buf.push(delta << 4 | length);
+            buf.extend_from_slice(&delta_ext);
+            buf.extend_from_slice(&length_ext);
+            buf.extend_from_slice(value);
+            last = *number;
+        }
+
+        if !self.payload.is_empty() {
+            buf.push(PAYLOAD_MARKER);
+            buf.extend_from_slice(&self.payload);
+        }
+
+        return buf;
+    }
+
+    pub fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < 4 {
+            return Err(anyhow!("Message too short"));
+        }
+        if buf[0] >> 6 != 1 {
+            return Err(anyhow!("Unsupported CoAP version"));
+        }
+
+        let mtype = match (buf[0] >> 4) & 0x03 {
+            0 => MessageType::Confirmable,
+            1 => MessageType::NonConfirmable,
+            2 => MessageType::Acknowledgement,
+            _ => MessageType::Reset,
+        };
+        let token_len = (buf[0] & 0x0f) as usize;
+        if token_len > 8 || buf.len() < 4 + token_len {
+            return Err(anyhow!("Invalid token length"));
+        }
+
+        let mut msg = Message::new(
+            mtype,
+            buf[1],
+            u16::from_be_bytes([buf[2], buf[3]]),
+            buf[4..4 + token_len].to_vec(),
+        );
+
+        let mut pos = 4 + token_len;
+        let mut number: u16 = 0;
+        while pos < buf.len() {
+            if buf[pos] == PAYLOAD_MARKER {
+                msg.payload = buf[pos + 1..].to_vec();
+                break;
+            }
+
+            let header = buf[pos];
+            pos += 1;
+            let delta = decode_option_nibble(header >> 4, buf, &mut pos)?;
+            let length = decode_option_nibble(header & 0x0f, buf, &mut pos)? as usize;
+
+            let value = buf.get(pos..pos + length).ok_or(anyhow!("Option exceeds message"))?;
+            number = number.checked_add(delta).ok_or(anyhow!("Option number overflows"))?;
+            msg.options.push((number, value.to_vec()));
+            pos += length;
+        }
+
+        return Ok(msg);
+    }
+}
+
+fn encode_option_nibble(value: u16) -> (u8, Vec<u8>) {
+    match value {
+        0..=12 => (value as u8, Vec::new()),
+        13..=268 => (13, vec![(value - 13) as u8]),
+        _ => (14, (value - 269).to_be_bytes().to_vec()),
+    }
+}
+
+fn decode_option_nibble(nibble: u8, buf: &[u8], pos: &mut usize) -> Result<u16> {
+    let value = match nibble {
+        0..=12 => nibble as u16,
+        13 => {
+            let ext = *buf.get(*pos).ok_or(anyhow!("Truncated option"))?;
+            *pos += 1;
+            ext as u16 + 13
+        }
+        14 => {
+            let ext = buf.get(*pos..*pos + 2).ok_or(anyhow!("Truncated option"))?;
+            *pos += 2;
+            u16::from_be_bytes([ext[0], ext[1]]).checked_add(269).ok_or(anyhow!("Option value overflows"))?
+        }
+        _ => return Err(anyhow!("Invalid option nibble")),
+    };
+    return Ok(value);
+}
+
+/// Formats a code the way RFC 7252 writes it, e.g. 4.04.
+pub fn format_code(code: u8) -> String {
+    return format!("{}.{:02}", code >> 5, code & 0x1f);
+}
+
+/// Encodes an unsigned option value with the minimal number of bytes.
+pub fn encode_uint(value: u32) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(4);
+    return bytes[start..].to_vec();
+}
+
+pub fn decode_uint(value: &[u8]) -> u32 {
+    return value.iter().take(4).fold(0, |acc, b| acc << 8 | *b as u32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut msg = Message::new(MessageType::Confirmable, CODE_POST, 0x1234, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        msg.set_uri_path("rd/1");
+        msg.add_option(OPTION_URI_QUERY, b"ep=bench".to_vec());
+        msg.add_option(OPTION_OBSERVE, encode_uint(0));
+        msg.payload = vec![0xff, 0x00, 0xff];
+
+        let decoded = Message::decode(&msg.encode()).unwrap();
+        assert_eq!(decoded.mtype, MessageType::Confirmable);
+        assert_eq!(decoded.code, CODE_POST);
+        assert_eq!(decoded.message_id, 0x1234);
+        assert_eq!(decoded.token, msg.token);
+        assert_eq!(decoded.options, msg.options);
+        assert_eq!(decoded.payload, msg.payload);
+        assert_eq!(decoded.uri_path(), "rd/1");
+        assert_eq!(decoded.uri_query("ep").as_deref(), Some("bench"));
+    }
+
+    #[test]
+    fn extended_nibbles() {
+        let mut msg = Message::empty(MessageType::NonConfirmable, 1);
+        // Delta 13 and a value length of 300 take the one and two byte extensions
+        msg.add_option(13, vec![7; 13]);
+        msg.add_option(13 + 300, vec![7; 300]);
+        msg.add_option(u16::MAX, Vec::new());
+
+        let buf = msg.encode();
+        assert_eq!(buf[4], 13 << 4 | 13);
+        assert_eq!(buf[5..7], [0, 0]);
+
+        let decoded = Message::decode(&buf).unwrap();
+        assert_eq!(decoded.options, msg.options);
+        assert!(decoded.payload.is_empty());
+    }
+
+    #[test]
+    fn payload_marker() {
+        // No marker without payload, the marker alone leaves the payload empty
+        let msg = Message::new(MessageType::Acknowledgement, CODE_CONTENT, 2, Vec::new());
+        assert_eq!(msg.encode(), [0x60, CODE_CONTENT, 0, 2]);
+
+        let decoded = Message::decode(&[0x60, CODE_CONTENT, 0, 2, PAYLOAD_MARKER]).unwrap();
+        assert!(decoded.payload.is_empty());
+    }
+
+    #[test]
+    fn decode_overflows() {
+        // Option number 65535 followed by a delta of 1
+        let number_overflow = [0x50, CODE_GET, 0, 0, 0xe0, 0xfe, 0xf2, 0x10];
+        assert!(Message::decode(&number_overflow).is_err());
+
+        // The two byte extension plus 269 exceeds u16
+        let value_overflow = [0x50, CODE_GET, 0, 0, 0xe0, 0xff, 0xff];
+        assert!(Message::decode(&value_overflow).is_err());
+
+        let truncated = [0x50, CODE_GET, 0, 0, 0x03, 1];
+        assert!(Message::decode(&truncated).is_err());
+
+        let reserved_nibble = [0x50, CODE_GET, 0, 0, 0xf0];
+        assert!(Message::decode(&reserved_nibble).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Option value longer than 65535 bytes")]
+    fn encode_overlong_option() {
+        let mut msg = Message::empty(MessageType::NonConfirmable, 1);
+        msg.add_option(OPTION_URI_PATH, vec![0; 65536]);
+        msg.encode();
+    }
+
+    #[test]
+    #[should_panic(expected = "Options not sorted by number")]
+    fn encode_unsorted_options() {
+        let mut msg = Message::empty(MessageType::NonConfirmable, 1);
+        msg.options = vec![(OPTION_URI_PATH, Vec::new()), (OPTION_OBSERVE, Vec::new())];
+        msg.encode();
+    }
+}
@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
@@ -6,6 +6,24 @@ pub struct Config {
     pub websocket: WebsocketConfig,
     pub tcp: TcpConfig,
     pub opcua: OpcuaConfig,
+    pub dds: DdsConfig,
+    pub ros2: Ros2Config,
+    pub coap: CoapConfig,
+    pub udp: UdpConfig,
+    pub local: LocalConfig,
+    pub amqp: AmqpConfig,
+    pub zmq: ZmqConfig,
+    pub nats: NatsConfig,
+    pub zenoh: ZenohConfig,
+    pub grpc: GrpcConfig,
+    pub http: HttpConfig,
+    pub sse: SseConfig,
+    pub quic: QuicConfig,
+    pub mqttsn: MqttSnConfig,
+    pub modbus: ModbusConfig,
+    pub kafka: KafkaConfig,
+    pub lwm2m: Lwm2mConfig,
+    pub redis: RedisConfig,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -15,13 +33,59 @@ pub struct MqttConfig {
     pub message_size: usize,
     pub topic_send: String,
     pub topic_recv: String,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MqttSnConfig {
+    /// UDP address of the gateway, which forwards to the broker and topics of `[mqtt]`.
+    pub gateway_address: String,
+    /// -1, 0 or 1, with -1 the messages are published without connecting.
+    #[serde(default)]
+    pub qos: i8,
+    /// Topic ID the gateway maps to `topic_send` before any client registers.
+    #[serde(default = "default_predefined_topic_id")]
+    pub predefined_topic_id: u16,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+fn default_predefined_topic_id() -> u16 {
+    1
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct WebsocketConfig {
     pub address: String,
+    #[serde(default)]
+    pub protocol: WebsocketProtocol,
+    /// Destination STOMP frames are sent to and subscribed on.
+    #[serde(default = "default_stomp_destination")]
+    pub destination: String,
+    /// Ask for a RECEIPT to every STOMP SEND frame.
+    #[serde(default)]
+    pub receipts: bool,
     pub schedule: ScheduleConfig,
     pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WebsocketProtocol {
+    /// Every message is a binary websocket frame.
+    #[default]
+    Raw,
+    /// Every message is a STOMP 1.2 SEND frame, echoed as MESSAGE frame.
+    Stomp,
+}
+
+fn default_stomp_destination() -> String {
+    "/queue/bench".to_string()
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -29,6 +93,8 @@ pub struct TcpConfig {
     pub address: String,
     pub schedule: ScheduleConfig,
     pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -36,6 +102,691 @@ pub struct OpcuaConfig {
     pub address: String,
     pub schedule: ScheduleConfig,
     pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ModbusConfig {
+    pub address: String,
+    #[serde(default = "default_modbus_unit_id")]
+    pub unit_id: u8,
+    /// First register the messages are written to and polled from.
+    #[serde(default)]
+    pub register_address: u16,
+    /// Pause between polls of the input registers, 0 polls as fast as the slave answers.
+    #[serde(default)]
+    pub poll_interval_ms: u64,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+fn default_modbus_unit_id() -> u8 {
+    1
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct KafkaConfig {
+    pub address: String,
+    pub topic: String,
+    /// Created with this many partitions if it does not exist yet.
+    #[serde(default = "default_kafka_partitions")]
+    pub partitions: i32,
+    #[serde(default = "default_kafka_group_id")]
+    pub group_id: String,
+    /// "0", "1" or "all"
+    #[serde(default = "default_kafka_acks")]
+    pub acks: String,
+    #[serde(default = "default_kafka_linger_ms")]
+    pub linger_ms: u64,
+    /// Maximum size of a batch in bytes.
+    #[serde(default = "default_kafka_batch_size")]
+    pub batch_size: usize,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+fn default_kafka_partitions() -> i32 {
+    1
+}
+
+fn default_kafka_group_id() -> String {
+    "kafka-bench".to_string()
+}
+
+fn default_kafka_acks() -> String {
+    "all".to_string()
+}
+
+fn default_kafka_linger_ms() -> u64 {
+    5
+}
+
+fn default_kafka_batch_size() -> usize {
+    1000000
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Lwm2mConfig {
+    /// Where the bench listens as LwM2M server for the client to register.
+    pub address: String,
+    #[serde(default = "default_lwm2m_endpoint")]
+    pub endpoint: String,
+    /// Registration lifetime, the client updates its registration halfway through.
+    #[serde(default = "default_lwm2m_lifetime_secs")]
+    pub lifetime_secs: u64,
+    /// Operations issued in turn, one per message.
+    #[serde(default = "default_lwm2m_operations")]
+    pub operations: Vec<Lwm2mOperation>,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Lwm2mOperation {
+    /// Read the binary data resource.
+    Read,
+    /// Write the message into the binary data resource.
+    Write,
+    /// Execute a resource without side effects.
+    Execute,
+}
+
+fn default_lwm2m_endpoint() -> String {
+    "iot-bench".to_string()
+}
+
+fn default_lwm2m_lifetime_secs() -> u64 {
+    30
+}
+
+fn default_lwm2m_operations() -> Vec<Lwm2mOperation> {
+    vec![Lwm2mOperation::Read, Lwm2mOperation::Write, Lwm2mOperation::Execute]
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct RedisConfig {
+    pub address: String,
+    #[serde(default)]
+    pub mode: RedisMode,
+    /// Channel, or stream key, the bench publishes to and the echo listens on.
+    pub channel_send: String,
+    pub channel_recv: String,
+    /// Consumer group reading the streams, on both sides.
+    #[serde(default = "default_redis_group")]
+    pub group: String,
+    /// Streams are trimmed to about this many entries.
+    #[serde(default = "default_redis_max_len")]
+    pub max_len: usize,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedisMode {
+    /// PUBLISH to the echo, which publishes on `channel_recv`.
+    #[default]
+    PubSub,
+    /// XADD to a stream the echo reads with XREADGROUP, echoed into another stream.
+    Streams,
+}
+
+impl RedisMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RedisMode::PubSub => "pub_sub",
+            RedisMode::Streams => "streams",
+        }
+    }
+}
+
+fn default_redis_group() -> String {
+    "bench".to_string()
+}
+
+fn default_redis_max_len() -> usize {
+    100000
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DdsConfig {
+    #[serde(default)]
+    pub domain_id: u16,
+    #[serde(default)]
+    pub qos: DdsQosConfig,
+    /// Runs the schedule once per profile instead of once with `qos`.
+    #[serde(default)]
+    pub profiles: Vec<DdsQosProfile>,
+    /// Use keyed topics with this many instances, topics have no key when unset.
+    pub instances: Option<u32>,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DdsQosProfile {
+    /// Appended to the out file name, so every profile gets a file of its own.
+    pub name: String,
+    #[serde(flatten)]
+    pub qos: DdsQosConfig,
+}
+
+/// QoS of the bench and echo participants, serialized to hand it to the echo.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DdsQosConfig {
+    #[serde(default)]
+    pub reliability: DdsReliability,
+    /// How long a reliable writer may block when the history is full.
+    #[serde(default)]
+    pub max_blocking_time_ms: u64,
+    #[serde(default)]
+    pub durability: DdsDurability,
+    /// Samples kept per instance, 0 keeps all of them.
+    #[serde(default = "default_dds_history_depth")]
+    pub history_depth: i32,
+    pub deadline_ms: Option<u64>,
+    #[serde(default)]
+    pub liveliness: DdsLiveliness,
+    /// Infinite when unset.
+    pub lease_duration_ms: Option<u64>,
+    /// Resource limits, unlimited when unset.
+    pub max_samples: Option<i32>,
+    pub max_instances: Option<i32>,
+    pub max_samples_per_instance: Option<i32>,
+}
+
+impl Default for DdsQosConfig {
+    fn default() -> Self {
+        Self {
+            reliability: DdsReliability::default(),
+            max_blocking_time_ms: 0,
+            durability: DdsDurability::default(),
+            history_depth: default_dds_history_depth(),
+            deadline_ms: None,
+            liveliness: DdsLiveliness::default(),
+            lease_duration_ms: None,
+            max_samples: None,
+            max_instances: None,
+            max_samples_per_instance: None,
+        }
+    }
+}
+
+fn default_dds_history_depth() -> i32 {
+    1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DdsReliability {
+    #[default]
+    Reliable,
+    BestEffort,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DdsDurability {
+    #[default]
+    Volatile,
+    /// Late joining readers get the samples still in the writer history.
+    TransientLocal,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DdsLiveliness {
+    #[default]
+    Automatic,
+    ManualByParticipant,
+    ManualByTopic,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ZenohConfig {
+    #[serde(default)]
+    pub mode: ZenohMode,
+    #[serde(default)]
+    pub pattern: ZenohPattern,
+    /// Locators like `tcp/127.0.0.1:7447`, the echo listens on them in peer mode,
+    /// in client mode both connect to the router there.
+    pub endpoints: Vec<String>,
+    #[serde(default)]
+    pub qos: ZenohQosConfig,
+    /// Key expressions of the requests, the responses and the queryable.
+    #[serde(default = "default_zenoh_key_req")]
+    pub key_req: String,
+    #[serde(default = "default_zenoh_key_rsp")]
+    pub key_rsp: String,
+    #[serde(default = "default_zenoh_key_query")]
+    pub key_query: String,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ZenohMode {
+    #[default]
+    Peer,
+    Client,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ZenohPattern {
+    /// Put on the request key, the echo puts on the response key.
+    #[default]
+    PubSub,
+    /// Get with the message as payload, answered by the echo's queryable.
+    Query,
+}
+
+/// QoS of the bench's and the echo's publications and queries, serialized to hand it to the echo.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ZenohQosConfig {
+    #[serde(default)]
+    pub reliability: ZenohReliability,
+    #[serde(default)]
+    pub congestion_control: ZenohCongestionControl,
+    #[serde(default)]
+    pub priority: ZenohPriority,
+    /// Send right away instead of batching with other messages.
+    #[serde(default)]
+    pub express: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ZenohReliability {
+    #[default]
+    Reliable,
+    BestEffort,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ZenohCongestionControl {
+    /// Wait for the queues to drain, like a reliable DDS writer.
+    #[default]
+    Block,
+    /// Drop messages when the queues are full.
+    Drop,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ZenohPriority {
+    RealTime,
+    InteractiveHigh,
+    InteractiveLow,
+    DataHigh,
+    #[default]
+    Data,
+    DataLow,
+    Background,
+}
+
+fn default_zenoh_key_req() -> String {
+    "zenoh_req".to_string()
+}
+
+fn default_zenoh_key_rsp() -> String {
+    "zenoh_rsp".to_string()
+}
+
+fn default_zenoh_key_query() -> String {
+    "zenoh_query".to_string()
+}
+
+impl ZenohMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ZenohMode::Peer => "peer",
+            ZenohMode::Client => "client",
+        }
+    }
+}
+
+impl ZenohPattern {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ZenohPattern::PubSub => "pub_sub",
+            ZenohPattern::Query => "query",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Ros2Config {
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GrpcConfig {
+    pub address: String,
+    #[serde(default)]
+    pub mode: GrpcMode,
+    /// HTTP/2 flow-control windows in bytes, the defaults of h2 are kept if unset.
+    pub stream_window_size: Option<u32>,
+    pub connection_window_size: Option<u32>,
+    /// Streams the echo server allows per connection, which bounds the unary calls in flight.
+    pub max_concurrent_streams: Option<u32>,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GrpcMode {
+    /// One unary call per message.
+    #[default]
+    Unary,
+    /// All messages on one bidirectional stream.
+    Streaming,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct HttpConfig {
+    pub address: String,
+    /// Path the messages are posted to.
+    #[serde(default = "default_http_path")]
+    pub path: String,
+    #[serde(default)]
+    pub mode: HttpMode,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HttpMode {
+    /// HTTP/1.1, one request at a time on a persistent connection.
+    #[default]
+    KeepAlive,
+    /// HTTP/1.1, a fresh TCP connection for every request.
+    NewConnection,
+    /// HTTP/1.1, requests go out without waiting for the previous responses.
+    Pipelined,
+    /// HTTP/2 over cleartext, every request on its own stream of one connection.
+    Http2,
+}
+
+fn default_http_path() -> String {
+    "/echo".to_string()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SseConfig {
+    pub address: String,
+    /// Events the echo keeps for replaying to clients reconnecting with `Last-Event-ID`.
+    #[serde(default = "default_sse_history_size")]
+    pub history_size: usize,
+    /// The echo closes each event stream after this many events, forcing the client to reconnect.
+    pub reconnect_after: Option<usize>,
+    /// Reconnection delay announced by the echo in the `retry` field.
+    #[serde(default = "default_sse_retry_ms")]
+    pub retry_ms: u64,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+fn default_sse_history_size() -> usize {
+    1024
+}
+
+fn default_sse_retry_ms() -> u64 {
+    100
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct QuicConfig {
+    pub address: String,
+    #[serde(default)]
+    pub mode: QuicMode,
+    /// Name the echo's self-signed certificate is issued for.
+    #[serde(default = "default_quic_server_name")]
+    pub server_name: String,
+    /// Where the echo stores its certificate (DER), which the bench then trusts.
+    pub cert_path: String,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum QuicMode {
+    /// All messages on one long-lived bidirectional stream.
+    #[default]
+    Stream,
+    /// A new bidirectional stream for every message.
+    StreamPerMessage,
+    /// Unreliable datagrams, no retransmission of lost messages.
+    Datagram,
+}
+
+fn default_quic_server_name() -> String {
+    "localhost".to_string()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NatsConfig {
+    pub address: String,
+    #[serde(default)]
+    pub mode: NatsMode,
+    pub subject_send: String,
+    pub subject_recv: String,
+    /// JetStream stream capturing `subject_send`.
+    #[serde(default = "default_nats_stream")]
+    pub stream: String,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NatsMode {
+    /// Publish to the echo, which publishes on `subject_recv`.
+    #[default]
+    PubSub,
+    /// Publish with a reply inbox the echo responds to.
+    Request,
+    /// Publish into a stream, which acknowledges each message, the echo consumes the stream.
+    JetStream,
+}
+
+impl NatsMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NatsMode::PubSub => "pub_sub",
+            NatsMode::Request => "request",
+            NatsMode::JetStream => "jet_stream",
+        }
+    }
+}
+
+fn default_nats_stream() -> String {
+    "BENCH".to_string()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ZmqConfig {
+    #[serde(default)]
+    pub pattern: ZmqPattern,
+    /// Endpoint the echo binds, e.g. `tcp://127.0.0.1:5555` or `ipc:///tmp/zmq-bench`.
+    pub endpoint: String,
+    /// Endpoint the echo publishes on, only used by `pub_sub`.
+    pub endpoint_recv: Option<String>,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ZmqPattern {
+    /// Closed loop, the next request is sent once the reply arrived. Requests queue up
+    /// in the meantime and the time they wait counts towards their latency.
+    #[default]
+    ReqRep,
+    /// Open loop over a subscription to the echo's publisher.
+    PubSub,
+    /// Open loop with replies routed back to the dealer.
+    DealerRouter,
+}
+
+impl ZmqPattern {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ZmqPattern::ReqRep => "req_rep",
+            ZmqPattern::PubSub => "pub_sub",
+            ZmqPattern::DealerRouter => "dealer_router",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AmqpConfig {
+    pub address: String,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    /// Direct exchange both queues are bound to, with their names as routing keys.
+    pub exchange: String,
+    pub queue_send: String,
+    pub queue_recv: String,
+    /// Wait for the broker to confirm every published message.
+    #[serde(default)]
+    pub confirms: bool,
+    /// Unacknowledged deliveries the broker sends ahead, 0 is unlimited.
+    #[serde(default)]
+    pub prefetch: u16,
+    /// Durable queues with persistent messages instead of transient ones.
+    #[serde(default)]
+    pub durable: bool,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct UdpConfig {
+    pub address: String,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    /// Socket buffer sizes in bytes, the OS defaults are kept if unset.
+    pub recv_buffer_size: Option<usize>,
+    pub send_buffer_size: Option<usize>,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LocalConfig {
+    #[serde(default)]
+    pub transport: LocalTransport,
+    /// Socket path, or the shared memory file for `shm`.
+    pub path: String,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    #[serde(default = "default_ring_slots")]
+    pub ring_slots: usize,
+    #[serde(default = "default_ring_slot_size")]
+    pub ring_slot_size: usize,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LocalTransport {
+    /// Unix domain stream socket.
+    #[default]
+    Stream,
+    /// Unix domain datagram socket.
+    Datagram,
+    /// Ring buffers in a shared memory file.
+    Shm,
+}
+
+impl LocalTransport {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LocalTransport::Stream => "stream",
+            LocalTransport::Datagram => "datagram",
+            LocalTransport::Shm => "shm",
+        }
+    }
+}
+
+fn default_ring_slots() -> usize {
+    1024
+}
+
+fn default_ring_slot_size() -> usize {
+    4096
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CoapConfig {
+    pub address: String,
+    pub schedule: ScheduleConfig,
+    pub message_size: usize,
+    #[serde(default)]
+    pub mode: CoapMode,
+    /// Send confirmable requests which are acknowledged and retransmitted.
+    #[serde(default)]
+    pub confirmable: bool,
+    #[serde(default = "default_ack_timeout_ms")]
+    pub ack_timeout_ms: u64,
+    #[serde(default = "default_max_retransmit")]
+    pub max_retransmit: u32,
+    pub out_file: Option<String>,
+    pub spawn: Option<SpawnConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CoapMode {
+    /// POST to the echo resource and measure until the response.
+    #[default]
+    Request,
+    /// PUT to an observed resource and measure until the notification.
+    Observe,
+}
+
+fn default_ack_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_max_retransmit() -> u32 {
+    4
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -44,4 +795,89 @@ pub struct ScheduleConfig {
     pub stop_req_per_sec: f64,
     pub steps: usize,
     pub secs_per_step: u64,
+    #[serde(default)]
+    pub warmup_secs: f64,
+    #[serde(default = "default_probe_timeout_secs")]
+    pub probe_timeout_secs: f64,
+    #[serde(default)]
+    pub drain: DrainConfig,
+}
+
+fn default_probe_timeout_secs() -> f64 {
+    10.
+}
+
+/// When to stop listening for echoes once all messages of a step are sent.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DrainPolicy {
+    /// Wait for the whole grace period.
+    Fixed,
+    /// Stop once no message arrived for `idle_ms`.
+    Idle,
+    /// Stop once all messages are received.
+    #[default]
+    All,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DrainConfig {
+    #[serde(default = "default_grace_secs")]
+    pub grace_secs: f64,
+    #[serde(default)]
+    pub policy: DrainPolicy,
+    #[serde(default = "default_idle_ms")]
+    pub idle_ms: u64,
+    /// Messages with a higher latency are counted as late instead of received.
+    pub late_after_ms: Option<u64>,
+    /// Keep listening this long after the grace period for missing messages, which then count as late instead of lost.
+    #[serde(default = "default_late_window_secs")]
+    pub late_window_secs: f64,
+}
+
+impl Default for DrainConfig {
+    fn default() -> Self {
+        Self {
+            grace_secs: default_grace_secs(),
+            policy: DrainPolicy::default(),
+            idle_ms: default_idle_ms(),
+            late_after_ms: None,
+            late_window_secs: default_late_window_secs(),
+        }
+    }
+}
+
+fn default_grace_secs() -> f64 {
+    5.
+}
+
+fn default_idle_ms() -> u64 {
+    500
+}
+
+fn default_late_window_secs() -> f64 {
+    2.
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SpawnConfig {
+    #[serde(default = "default_spawn_echo")]
+    pub echo: bool,
+    pub broker: Option<String>,
+    #[serde(default = "default_ready_timeout_secs")]
+    pub ready_timeout_secs: u64,
+    #[serde(default = "default_log_dir")]
+    pub log_dir: String,
+}
+
+fn default_spawn_echo() -> bool {
+    true
+}
+
+fn default_ready_timeout_secs() -> u64 {
+    10
+}
+
+fn default_log_dir() -> String {
+    "logs".to_string()
 }
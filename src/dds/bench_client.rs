@@ -1,151 +1,226 @@
-use rustdds::{DomainParticipant, CDRSerializerAdapter, CDRDeserializerAdapter, QosPolicyBuilder, TopicKind};
-use rustdds::no_key::{DataWriter, DataReader};
-use rustdds::policy;
-use anyhow::Result;
-use std::time::{Duration, Instant};
-use mio::{Events, Interest, Poll, Token};
+use rustdds::{DomainParticipant, QosPolicies};
+use std::path::Path;
+use std::time::Duration;
+use mio::{Events, Poll, Token};
 
 #[path="../benchmarker.rs"]
 mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure, index_from_message};
 
-use benchmarker::{ClientStats, BenchSettings};
-use benchmarker::run_benchmark;
+#[path="../config.rs"]
+mod config;
+use config::{Config, DdsConfig, DdsQosConfig};
 
-fn dds_init_pub() -> (DataWriter<String>, DomainParticipant) {
-    let domain_participant = DomainParticipant::new(1).unwrap();
+#[path="../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
 
-    let qos = QosPolicyBuilder::new()
-      .reliability(policy::Reliability::Reliable { max_blocking_time: rustdds::Duration::DURATION_ZERO })
-      .build();
+mod qos;
+mod sample;
+use sample::{Reader, Sample, Writer};
 
-    let publisher = domain_participant.create_publisher(&qos).unwrap();
+const SUB_READY: Token = Token(1);
 
-    let topic = domain_participant
-        .create_topic("dds_rsp".to_string(), "JustAString".to_string(), &qos, TopicKind::NoKey)
-        .unwrap();
+struct DdsSender {
+    publisher: Writer,
+    /// Number of instances on keyed topics.
+    instances: u32,
+    // The participant has to outlive the writer
+    _participant: DomainParticipant,
+}
+
+impl DdsSender {
+    pub fn new(domain_id: u16, qos: &QosPolicies, instances: Option<u32>) -> BenchResult<Self> {
+        let domain_participant = DomainParticipant::new(domain_id).map_err(BenchError::connect)?;
+
+        let publisher = domain_participant.create_publisher(qos).map_err(BenchError::protocol)?;
+
+        let keyed = instances.is_some();
+        let topic = sample::create_topic(&domain_participant, "dds_req", qos, keyed)
+            .map_err(BenchError::protocol)?;
+
+        let publisher = Writer::new(&publisher, &topic, keyed).map_err(BenchError::protocol)?;
 
-    let publisher = publisher
-      .create_datawriter_no_key::<String, CDRSerializerAdapter<String>>(
-        &topic,
-        None)
-      .unwrap();
+        Ok(Self {
+            publisher,
+            instances: instances.unwrap_or(1),
+            _participant: domain_participant,
+        })
+    }
+}
+
+impl Sender for DdsSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        // The message number becomes the sequence number, the rest is the payload
+        let seq = index_from_message(msg.clone()).map_err(BenchError::protocol)?;
+        let sample = Sample::new(seq as u64, self.instances, msg[8..].to_vec());
+        return self.publisher.write(sample).map_err(BenchError::send);
+    }
+}
 
-    return (publisher, domain_participant);
+struct DdsReceiver {
+    subscriber: Reader,
+    poll: Poll,
+    events: Events,
+    /// Samples created before the step started, see `take`.
+    time_start_ns: u64,
+    num_replayed: usize,
+    _participant: DomainParticipant,
 }
 
-fn dds_init_sub() -> (DataReader<String>, DomainParticipant) {
-    let domain_participant = DomainParticipant::new(2).unwrap();
+impl DdsReceiver {
+    pub fn new(domain_id: u16, qos: &QosPolicies, keyed: bool) -> BenchResult<Self> {
+        let domain_participant = DomainParticipant::new(domain_id).map_err(BenchError::connect)?;
+
+        let subscriber = domain_participant.create_subscriber(qos).map_err(BenchError::protocol)?;
 
-    let qos = QosPolicyBuilder::new()
-      .reliability(policy::Reliability::Reliable { max_blocking_time: rustdds::Duration::DURATION_ZERO })
-      .build();
+        let topic = sample::create_topic(&domain_participant, "dds_rsp", qos, keyed)
+            .map_err(BenchError::protocol)?;
 
-    let subscriber = domain_participant.create_subscriber(&qos).unwrap();
+        let mut subscriber = Reader::new(&subscriber, &topic, keyed).map_err(BenchError::protocol)?;
 
-    let topic = domain_participant
-        .create_topic("dds_req".to_string(), "JustAString".to_string(), &qos, TopicKind::NoKey)
-        .unwrap();
+        let poll = Poll::new().map_err(BenchError::connect)?;
+        subscriber.register(poll.registry(), SUB_READY).map_err(BenchError::connect)?;
 
-    let subscriber = subscriber
-        .create_datareader_no_key::<String, CDRDeserializerAdapter<String>>(
-            &topic, 
-            None)
-        .unwrap();
+        Ok(Self {
+            subscriber,
+            poll,
+            events: Events::with_capacity(5),
+            time_start_ns: sample::now_ns(),
+            num_replayed: 0,
+            _participant: domain_participant,
+        })
+    }
 
-    return (subscriber, domain_participant);
+    fn take(&mut self) -> BenchResult<Option<MsgType>> {
+        while let Some(sample) = self.subscriber.take().map_err(BenchError::receive)? {
+            // With transient_local the echo replays the responses of earlier steps to the new reader,
+            // their sequence numbers would be taken for this step's messages
+            if sample.timestamp_ns < self.time_start_ns {
+                self.num_replayed += 1;
+                continue;
+            }
+
+            let mut msg = Vec::from((sample.seq as usize).to_ne_bytes());
+            msg.extend(sample.payload);
+            return Ok(Some(msg));
+        }
+        return Ok(None);
+    }
 }
 
-fn dds_send(pub_part: &mut (DataWriter<String>, DomainParticipant), msg: String) -> Result<()> {
-    let (publisher, _) = pub_part;
-    println!("publ");
-    return Ok(publisher.write(msg.to_string(), None)?);
+impl Drop for DdsReceiver {
+    fn drop(&mut self) {
+        if self.num_replayed > 0 {
+            println!("Ignored {} samples replayed from earlier steps", self.num_replayed);
+        }
+    }
 }
 
-fn dds_listen(sub_part: (DataReader<String>, DomainParticipant), duration: Duration) -> Result<ClientStats> {
-    let (mut subscriber, _) = sub_part;
-    let (mut num_recv, mut num_errors) = (0, 0);
-
-    let mut time_last_msg = Instant::now();
-    let time_start = Instant::now();
-    //while time_start.elapsed() < duration {
-    //    match subscriber.take_next_sample() {
-    //        Ok(Some(_msg)) => {
-    //            println!("listen");
-    //            num_recv += 1;
-    //            time_last_msg = Instant::now();
-    //        },
-    //        Err(_) => num_errors += 1,
-    //        _ => (),
-    //    };
-    //} 
-    const SUB_READY: Token = Token(1);
-    const SUB_STATUS_READY: Token = Token(2);
-
-    let mut poll = Poll::new().unwrap();
-    let mut events = Events::with_capacity(5);
-
-    poll.registry()
-        .register(&mut subscriber, SUB_READY, Interest::READABLE)
-        .unwrap();
-
-    //poll.registry()
-    //    .register(
-    //        sub.as_status_source(),
-    //        SUB_STATUS_READY,
-    //        Interest::READABLE,
-    //    )
-    //    .unwrap();
-
-    loop {
-        
-        if let Err(e) = poll.poll(&mut events, Some(std::time::Duration::from_millis(200))) {
-            println!("Poll error {e}");
+impl Receiver for DdsReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        // The reader only signals new data, so it has to be drained before polling again
+        if let Some(msg) = self.take()? {
+            return Ok(Some(msg));
         }
 
-        for event in &events {
-            match event.token() {
-                SUB_READY => {
-                    loop {
-                        println!("DataReader triggered");
-                        match subscriber.take_next_sample() {
-                            Ok(Some(sample)) => {
-                                println!("recv");
-                                num_recv += 1;
-                            },
-                            Ok(None) => break, // no more data
-                            Err(e) => println!("DataReader error: {e:?}")
-                        } 
-                    }
-                },
-                //SUB_STATUS_READY => {
-                //    while let Some(status) = subscriber.try_recv_status() {
-                //        println!("DataReader status: {status:?}");
-                //    }
-                //},
-                Token(_) => (),
-            } // match token
-        } // for
+        self.poll.poll(&mut self.events, Some(timeout)).map_err(BenchError::receive)?;
+        return self.take();
     }
+}
+
+fn run_bench(config: &DdsConfig, qos: &QosPolicies, out_file: &Option<String>, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
 
-    return Ok(ClientStats{
-        num: num_recv,
-        num_errors,
-        time_last_msg,
-        duration: time_start.elapsed(),
+    let send = DdsSender::new(config.domain_id, qos, config.instances)?;
+    let recv = DdsReceiver::new(config.domain_id, qos, config.instances.is_some())?;
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = out_file.clone();
+
+    return bench.run(send, recv);
+}
+
+/// Runs the whole schedule with `qos`, the echo is restarted to use it as well.
+fn run_schedule(config: &DdsConfig, qos: &DdsQosConfig, out_file: &Option<String>) {
+    let _supervisor = config.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "dds-echo",
+            &[
+                &config.domain_id.to_string(),
+                &toml::to_string(qos).unwrap(),
+                &config.instances.is_some().to_string(),
+            ],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
     });
+
+    let qos = qos::build(qos);
+    let schedule = config.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            config,
+            &qos,
+            out_file,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
 }
 
-fn main() {
-    let settings = BenchSettings{
-        fn_init_send: dds_init_pub,
-        fn_init_listen: dds_init_sub,
-        fn_send: dds_send,
-        fn_listen: dds_listen,
-        duration: Duration::from_secs(5),
-        msgs_per_sec: 1.,
-        message_len: 10,
-        out_file: "data/dds.json".to_string(),
+/// `data/dds.jsonl` becomes `data/dds-<name>.jsonl`.
+fn profile_out_file(out_file: &str, name: &str) -> String {
+    let path = Path::new(out_file);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{stem}-{name}.{}", ext.to_string_lossy()),
+        None => format!("{stem}-{name}"),
     };
+    return path.with_file_name(file_name).to_string_lossy().into_owned();
+}
+
+fn main() {
+    handle_interrupt();
 
-    run_benchmark(settings);
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    if config.dds.profiles.is_empty() {
+        run_schedule(&config.dds, &config.dds.qos, &config.dds.out_file);
+        return;
+    }
+
+    for profile in &config.dds.profiles {
+        println!("Running QoS profile {}", profile.name);
+        let out_file = config.dds.out_file.as_ref().map(|out_file| profile_out_file(out_file, &profile.name));
+        run_schedule(&config.dds, &profile.qos, &out_file);
+
+        if is_interrupted() {
+            break;
+        }
+    }
 }
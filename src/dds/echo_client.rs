@@ -1,36 +1,40 @@
 use rustdds::*;
-use mio::{Events, Interest, Poll, Token};
+use mio::{Events, Poll, Token};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[path="../config.rs"]
+mod config;
+use config::DdsQosConfig;
+
+mod qos;
+mod sample;
+use sample::{Reader, Writer};
 
 fn main() {
-    let domain_participant = DomainParticipant::new(0).unwrap();
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let domain_id = args.get(1).map_or(0, |id| id.parse::<u16>().expect("Invalid domain id"));
+    // The bench hands over its QoS serialized as TOML
+    let qos_config: DdsQosConfig = args.get(2).map_or(DdsQosConfig::default(), |qos| toml::from_str(qos).expect("Invalid QoS"));
+    println!("QoS: {qos_config:?}");
+    let keyed = args.get(3).is_some_and(|keyed| keyed == "true");
 
-    let qos = QosPolicyBuilder::new()
-      .reliability(policy::Reliability::Reliable { max_blocking_time: rustdds::Duration::DURATION_ZERO })
-      .build();
+    let domain_participant = DomainParticipant::new(domain_id).unwrap();
+    let qos = qos::build(&qos_config);
 
     let subscriber = domain_participant.create_subscriber(&qos).unwrap();
 
-    let topic_req = domain_participant
-        .create_topic("dds_req".to_string(), "JustAString".to_string(), &qos, TopicKind::NoKey)
-        .unwrap();
+    let topic_req = sample::create_topic(&domain_participant, "dds_req", &qos, keyed).unwrap();
 
-    let mut sub = subscriber
-        .create_datareader_no_key::<String, CDRDeserializerAdapter<String>>(
-            &topic_req, 
-            None)
-        .unwrap();
+    let mut sub = Reader::new(&subscriber, &topic_req, keyed).unwrap();
 
     let publisher = domain_participant.create_publisher(&qos).unwrap();
 
-    let topic_rsp = domain_participant
-        .create_topic("dds_rsp".to_string(), "JustAString".to_string(), &qos, TopicKind::NoKey)
-        .unwrap();
+    let topic_rsp = sample::create_topic(&domain_participant, "dds_rsp", &qos, keyed).unwrap();
 
-    let publ = publisher
-      .create_datawriter_no_key::<String, CDRSerializerAdapter<String>>(
-        &topic_rsp,
-        None)
-      .unwrap();
+    let publ = Writer::new(&publisher, &topic_rsp, keyed).unwrap();
 
     const SUB_READY: Token = Token(1);
     const SUB_STATUS_READY: Token = Token(2);
@@ -38,19 +42,21 @@ fn main() {
     let mut poll = Poll::new().unwrap();
     let mut events = Events::with_capacity(5);
 
-    poll.registry()
-        .register(&mut sub, SUB_READY, Interest::READABLE)
-        .unwrap();
+    sub.register(poll.registry(), SUB_READY).unwrap();
+    sub.register_status(poll.registry(), SUB_STATUS_READY).unwrap();
 
-    poll.registry()
-        .register(
-            sub.as_status_source(),
-            SUB_STATUS_READY,
-            Interest::READABLE,
-        )
-        .unwrap();
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
 
-    loop {
+    // Failed writes leave the request unanswered, the bench counts it as lost
+    let mut num_write_errors = 0;
+    let mut num_read_errors = 0;
+
+    println!("Waiting for messages..");
+    while running.load(Ordering::SeqCst) {
         
         if let Err(e) = poll.poll(&mut events, Some(std::time::Duration::from_millis(200))) {
             println!("Poll error {e}");
@@ -60,24 +66,34 @@ fn main() {
             match event.token() {
                 SUB_READY => {
                     loop {
-                        println!("DataReader triggered");
-                        match sub.take_next_sample() {
+                        match sub.take() {
                             Ok(Some(sample)) => {
-                                println!("recv");
-                                publ.write(sample.into_value(), None).unwrap();
+                                if let Err(e) = publ.write(sample) {
+                                    println!("DataWriter error: {e:?}");
+                                    num_write_errors += 1;
+                                }
                             },
                             Ok(None) => break, // no more data
-                            Err(e) => println!("DataReader error: {e:?}")
+                            // Taking again would fail the same way, the next event retries
+                            Err(e) => {
+                                println!("DataReader error: {e:?}");
+                                num_read_errors += 1;
+                                break;
+                            }
                         } 
                     }
                 },
-                SUB_STATUS_READY => {
-                    while let Some(status) = sub.try_recv_status() {
-                        println!("DataReader status: {status:?}");
-                    }
-                },
+                SUB_STATUS_READY => sub.print_statuses(),
                 Token(_) => (),
             } // match token
         } // for
     }
+
+    if num_write_errors > 0 {
+        println!("{num_write_errors} writes failed");
+    }
+    if num_read_errors > 0 {
+        println!("{num_read_errors} reads failed");
+    }
+    println!("Shutting down");
 }
@@ -0,0 +1,56 @@
+use rustdds::{policy, QosPolicies, QosPolicyBuilder};
+
+use crate::config::{DdsDurability, DdsLiveliness, DdsQosConfig, DdsReliability};
+
+/// Resource limit without a limit.
+const LENGTH_UNLIMITED: i32 = -1;
+
+/// QoS for the participants' topics, publishers and subscribers, readers and writers inherit it.
+pub fn build(config: &DdsQosConfig) -> QosPolicies {
+    let reliability = match config.reliability {
+        DdsReliability::Reliable => policy::Reliability::Reliable { max_blocking_time: millis(config.max_blocking_time_ms) },
+        DdsReliability::BestEffort => policy::Reliability::BestEffort,
+    };
+
+    let durability = match config.durability {
+        DdsDurability::Volatile => policy::Durability::Volatile,
+        DdsDurability::TransientLocal => policy::Durability::TransientLocal,
+    };
+
+    let history = if config.history_depth > 0 {
+        policy::History::KeepLast { depth: config.history_depth }
+    } else {
+        policy::History::KeepAll
+    };
+
+    let lease_duration = config.lease_duration_ms.map_or(rustdds::Duration::DURATION_INFINITE, millis);
+    let liveliness = match config.liveliness {
+        DdsLiveliness::Automatic => policy::Liveliness::Automatic { lease_duration },
+        DdsLiveliness::ManualByParticipant => policy::Liveliness::ManualByParticipant { lease_duration },
+        DdsLiveliness::ManualByTopic => policy::Liveliness::ManualByTopic { lease_duration },
+    };
+
+    let mut builder = QosPolicyBuilder::new()
+        .reliability(reliability)
+        .durability(durability)
+        .history(history)
+        .liveliness(liveliness);
+
+    if let Some(deadline_ms) = config.deadline_ms {
+        builder = builder.deadline(policy::Deadline(millis(deadline_ms)));
+    }
+
+    if config.max_samples.is_some() || config.max_instances.is_some() || config.max_samples_per_instance.is_some() {
+        builder = builder.resource_limits(policy::ResourceLimits {
+            max_samples: config.max_samples.unwrap_or(LENGTH_UNLIMITED),
+            max_instances: config.max_instances.unwrap_or(LENGTH_UNLIMITED),
+            max_samples_per_instance: config.max_samples_per_instance.unwrap_or(LENGTH_UNLIMITED),
+        });
+    }
+
+    return builder.build();
+}
+
+fn millis(ms: u64) -> rustdds::Duration {
+    return rustdds::Duration::from_millis(ms as i64);
+}
@@ -0,0 +1,140 @@
+use anyhow::Result;
+use mio::{Interest, Registry, Token};
+use rustdds::{no_key, with_key, CDRDeserializerAdapter, CDRSerializerAdapter, DomainParticipant, Keyed};
+use rustdds::{Publisher, QosPolicies, StatusEvented, Subscriber, Topic, TopicKind};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const TYPE_NAME: &str = "Sample";
+
+/// Data type of the request and response topics, the echo sends the samples back unchanged.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Sample {
+    /// Instance of the sample on keyed topics, the sequence number modulo the number of instances.
+    pub key: u32,
+    pub seq: u64,
+    /// Nanoseconds since the Unix epoch when the sample was created.
+    pub timestamp_ns: u64,
+    pub payload: Vec<u8>,
+}
+
+impl Sample {
+    pub fn new(seq: u64, instances: u32, payload: Vec<u8>) -> Self {
+        Self {
+            key: (seq % instances.max(1) as u64) as u32,
+            seq,
+            timestamp_ns: now_ns(),
+            payload,
+        }
+    }
+}
+
+/// Nanoseconds since the Unix epoch, like the sample timestamps.
+pub fn now_ns() -> u64 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_nanos() as u64);
+}
+
+impl Keyed for Sample {
+    type K = u32;
+
+    fn key(&self) -> u32 {
+        return self.key;
+    }
+}
+
+pub fn create_topic(participant: &DomainParticipant, name: &str, qos: &QosPolicies, keyed: bool) -> Result<Topic> {
+    let kind = if keyed { TopicKind::WithKey } else { TopicKind::NoKey };
+    return Ok(participant.create_topic(name.to_string(), TYPE_NAME.to_string(), qos, kind)?);
+}
+
+/// Writes to a topic with or without key, matching the kind of the topic.
+pub enum Writer {
+    NoKey(no_key::DataWriter<Sample>),
+    WithKey(with_key::DataWriter<Sample>),
+}
+
+impl Writer {
+    pub fn new(publisher: &Publisher, topic: &Topic, keyed: bool) -> Result<Self> {
+        if keyed {
+            let writer = publisher.create_datawriter::<Sample, CDRSerializerAdapter<Sample>>(topic, None)?;
+            return Ok(Writer::WithKey(writer));
+        }
+        let writer = publisher.create_datawriter_no_key::<Sample, CDRSerializerAdapter<Sample>>(topic, None)?;
+        return Ok(Writer::NoKey(writer));
+    }
+
+    pub fn write(&self, sample: Sample) -> Result<()> {
+        match self {
+            Writer::NoKey(writer) => writer.write(sample, None)?,
+            Writer::WithKey(writer) => writer.write(sample, None)?,
+        }
+        return Ok(());
+    }
+}
+
+/// Reads from a topic with or without key, matching the kind of the topic.
+pub enum Reader {
+    NoKey(no_key::DataReader<Sample>),
+    WithKey(with_key::DataReader<Sample>),
+}
+
+impl Reader {
+    pub fn new(subscriber: &Subscriber, topic: &Topic, keyed: bool) -> Result<Self> {
+        if keyed {
+            let reader = subscriber.create_datareader::<Sample, CDRDeserializerAdapter<Sample>>(topic, None)?;
+            return Ok(Reader::WithKey(reader));
+        }
+        let reader = subscriber.create_datareader_no_key::<Sample, CDRDeserializerAdapter<Sample>>(topic, None)?;
+        return Ok(Reader::NoKey(reader));
+    }
+
+    /// Takes the next sample, disposed instances are skipped.
+    pub fn take(&mut self) -> Result<Option<Sample>> {
+        match self {
+            Reader::NoKey(reader) => {
+                return Ok(reader.take_next_sample()?.map(|sample| sample.into_value()));
+            }
+            Reader::WithKey(reader) => {
+                while let Some(sample) = reader.take_next_sample()? {
+                    if let Ok(sample) = sample.into_value() {
+                        return Ok(Some(sample));
+                    }
+                }
+                return Ok(None);
+            }
+        }
+    }
+
+    /// The reader only signals new data, so it has to be drained with `take` after every event.
+    pub fn register(&mut self, registry: &Registry, token: Token) -> std::io::Result<()> {
+        match self {
+            Reader::NoKey(reader) => registry.register(reader, token, Interest::READABLE),
+            Reader::WithKey(reader) => registry.register(reader, token, Interest::READABLE),
+        }
+    }
+
+    pub fn register_status(&mut self, registry: &Registry, token: Token) -> std::io::Result<()> {
+        match self {
+            Reader::NoKey(reader) => registry.register(reader.as_status_source(), token, Interest::READABLE),
+            Reader::WithKey(reader) => registry.register(reader.as_status_source(), token, Interest::READABLE),
+        }
+    }
+
+    /// Prints the pending status changes, like missed deadlines or lost liveliness.
+    pub fn print_statuses(&mut self) {
+        match self {
+            Reader::NoKey(reader) => {
+                while let Some(status) = reader.try_recv_status() {
+                    println!("DataReader status: {status:?}");
+                }
+            }
+            Reader::WithKey(reader) => {
+                while let Some(status) = reader.try_recv_status() {
+                    println!("DataReader status: {status:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::anyhow;
+use futures::channel::mpsc::{unbounded, UnboundedSender};
+use std::sync::mpsc;
+use std::time::Duration;
+use tokio::runtime::{Handle, Runtime};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Status, Streaming};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, GrpcConfig, GrpcMode};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod echo;
+use echo::echo_client::EchoClient;
+use echo::EchoMessage;
+
+fn status_error(status: Status) -> BenchError {
+    if status.code() == Code::Unavailable {
+        return BenchError::connect(status);
+    }
+    return BenchError::receive(status);
+}
+
+/// Issues one unary call per message without waiting for the previous ones,
+/// the responses are collected by the calls themselves.
+struct UnarySender {
+    client: EchoClient<Channel>,
+    handle: Handle,
+    tx: mpsc::Sender<BenchResult<MsgType>>,
+}
+
+impl Sender for UnarySender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let mut client = self.client.clone();
+        let tx = self.tx.clone();
+
+        self.handle.spawn(async move {
+            let result = client.unary(EchoMessage { payload: msg }).await
+                .map(|response| response.into_inner().payload)
+                .map_err(status_error);
+            let _ = tx.send(result);
+        });
+        return Ok(());
+    }
+}
+
+struct UnaryReceiver {
+    rx: mpsc::Receiver<BenchResult<MsgType>>,
+}
+
+impl Receiver for UnaryReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(result) => result.map(Some),
+            // The sender holds a handle as well, so this only times out
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+struct StreamSender {
+    tx: UnboundedSender<EchoMessage>,
+}
+
+impl Sender for StreamSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        return self.tx.unbounded_send(EchoMessage { payload: msg }).map_err(BenchError::connect);
+    }
+}
+
+struct StreamReceiver {
+    stream: Streaming<EchoMessage>,
+    handle: Handle,
+}
+
+impl Receiver for StreamReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let next = self.handle.block_on(async {
+            return tokio::time::timeout(timeout, self.stream.message()).await;
+        });
+
+        match next {
+            Ok(Ok(Some(msg))) => Ok(Some(msg.payload)),
+            Ok(Ok(None)) => Err(BenchError::connect(anyhow!("Stream closed by server"))),
+            Ok(Err(status)) => Err(status_error(status)),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+fn run_bench(config: &GrpcConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let runtime = Runtime::new().map_err(BenchError::connect)?;
+    let handle = runtime.handle().clone();
+
+    let endpoint = Endpoint::from_shared(format!("http://{}", config.address))
+        .map_err(BenchError::connect)?
+        .initial_stream_window_size(config.stream_window_size)
+        .initial_connection_window_size(config.connection_window_size);
+    let channel = runtime.block_on(endpoint.connect()).map_err(BenchError::connect)?;
+    let mut client = EchoClient::new(channel);
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    match config.mode {
+        GrpcMode::Unary => {
+            let (tx, rx) = mpsc::channel();
+            let send = UnarySender { client, handle, tx };
+            return bench.run(send, UnaryReceiver { rx });
+        }
+        GrpcMode::Streaming => {
+            let (tx, requests) = unbounded();
+            let stream = runtime.block_on(client.streaming(requests))
+                .map_err(status_error)?
+                .into_inner();
+
+            let send = StreamSender { tx };
+            return bench.run(send, StreamReceiver { stream, handle });
+        }
+    }
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.grpc.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "grpc-echo",
+            &[
+                &config.grpc.address,
+                &config.grpc.max_concurrent_streams.unwrap_or(0).to_string(),
+                &config.grpc.stream_window_size.unwrap_or(0).to_string(),
+                &config.grpc.connection_window_size.unwrap_or(0).to_string(),
+            ],
+            Readiness::Listening(config.grpc.address.clone()),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.grpc.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.grpc,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.grpc.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,9 @@
+/// The benchmark message, carried as single protobuf bytes field.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct EchoMessage {
+    #[prost(bytes = "vec", tag = "1")]
+    pub payload: Vec<u8>,
+}
+
+// Client and server of the Echo service, generated by build.rs
+include!(concat!(env!("OUT_DIR"), "/bench.Echo.rs"));
@@ -0,0 +1,69 @@
+use anyhow::Result;
+use futures::channel::oneshot;
+use futures::Stream;
+use std::pin::Pin;
+use std::sync::Mutex;
+use tokio::runtime::Runtime;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+mod echo;
+use echo::echo_server::{Echo, EchoServer};
+use echo::EchoMessage;
+
+struct EchoService;
+
+#[tonic::async_trait]
+impl Echo for EchoService {
+    async fn unary(&self, request: Request<EchoMessage>) -> Result<Response<EchoMessage>, Status> {
+        return Ok(Response::new(request.into_inner()));
+    }
+
+    type StreamingStream = Pin<Box<dyn Stream<Item = Result<EchoMessage, Status>> + Send>>;
+
+    async fn streaming(
+        &self,
+        request: Request<Streaming<EchoMessage>>,
+    ) -> Result<Response<Self::StreamingStream>, Status> {
+        // Every incoming message goes straight back on the response stream
+        return Ok(Response::new(Box::pin(request.into_inner())));
+    }
+}
+
+/// Parses an optional numeric argument, where 0 keeps the default.
+fn limit_arg(arg: Option<&String>) -> Option<u32> {
+    return arg.and_then(|limit| limit.parse().ok()).filter(|limit| *limit > 0);
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let addr_default = "127.0.0.1:50051".to_string();
+    let addr = args.get(1).unwrap_or(&addr_default).parse()?;
+    let max_concurrent_streams = limit_arg(args.get(2));
+    let stream_window_size = limit_arg(args.get(3));
+    let connection_window_size = limit_arg(args.get(4));
+
+    let (exit_tx, exit_rx) = oneshot::channel::<()>();
+    let exit_tx = Mutex::new(Some(exit_tx));
+    ctrlc::set_handler(move || {
+        if let Some(tx) = exit_tx.lock().unwrap().take() {
+            let _ = tx.send(());
+        }
+    }).expect("Error setting up exit handler");
+
+    let server = Server::builder()
+        .max_concurrent_streams(max_concurrent_streams)
+        .initial_stream_window_size(stream_window_size)
+        .initial_connection_window_size(connection_window_size)
+        .add_service(EchoServer::new(EchoService));
+
+    println!("Listening on {addr}");
+    Runtime::new()?.block_on(server.serve_with_shutdown(addr, async {
+        let _ = exit_rx.await;
+    }))?;
+
+    println!("Shutting down");
+    return Ok(());
+}
@@ -0,0 +1,280 @@
+use anyhow::anyhow;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::client::conn::http2;
+use hyper::header::CONTENT_TYPE;
+use hyper::Request;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::io::{prelude::*, ErrorKind};
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use tokio::runtime::{Handle, Runtime};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, HttpConfig, HttpMode};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod message;
+
+/// An HTTP/1.1 connection collecting responses until a complete one is buffered.
+struct Connection {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+impl Connection {
+    fn open(address: &str) -> BenchResult<Self> {
+        let stream = TcpStream::connect(address).map_err(BenchError::connect)?;
+        stream.set_nodelay(true).map_err(BenchError::connect)?;
+        return Ok(Self { stream, buffer: Vec::new() });
+    }
+
+    fn read_response(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let time_start = Instant::now();
+        loop {
+            if let Some(body) = message::decode_response(&mut self.buffer).map_err(BenchError::protocol)? {
+                return Ok(Some(body));
+            }
+
+            let remaining = timeout.saturating_sub(time_start.elapsed());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            self.stream.set_read_timeout(Some(remaining)).map_err(BenchError::receive)?;
+
+            let mut chunk = [0u8; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(BenchError::connect(anyhow!("disconnected"))),
+                Ok(size) => self.buffer.extend_from_slice(&chunk[..size]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None);
+                }
+                Err(e) => return Err(BenchError::receive(e)),
+            }
+        }
+    }
+}
+
+/// Sends a request and waits for its response before the next one can go out.
+struct RequestSender {
+    address: String,
+    path: String,
+    keep_alive: bool,
+    connection: Option<Connection>,
+    response_timeout: Duration,
+    tx: mpsc::Sender<BenchResult<MsgType>>,
+}
+
+impl Sender for RequestSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => Connection::open(&self.address)?,
+        };
+
+        let request = message::encode_request(&self.address, &self.path, &msg, !self.keep_alive);
+        let result = connection.stream.write_all(&request).map_err(BenchError::send)
+            .and_then(|_| connection.read_response(self.response_timeout));
+
+        match result {
+            Ok(Some(body)) => {
+                // A late response would be mistaken for the next one, so the connection
+                // is only reused after a complete exchange
+                if self.keep_alive {
+                    self.connection = Some(connection);
+                }
+                let _ = self.tx.send(Ok(body));
+            }
+            Ok(None) => {}
+            // The server dropped the connection, the next request opens a new one
+            Err(BenchError::Connect(e)) => return Err(BenchError::send(e)),
+            Err(e) => return Err(e),
+        }
+        return Ok(());
+    }
+}
+
+struct PipelineSender {
+    stream: TcpStream,
+    address: String,
+    path: String,
+}
+
+impl Sender for PipelineSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let request = message::encode_request(&self.address, &self.path, &msg, false);
+        return self.stream.write_all(&request).map_err(BenchError::send);
+    }
+}
+
+struct PipelineReceiver {
+    connection: Connection,
+}
+
+impl Receiver for PipelineReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        return self.connection.read_response(timeout);
+    }
+}
+
+/// Sends every request on its own stream without waiting for the previous ones.
+struct Http2Sender {
+    sender: http2::SendRequest<Full<Bytes>>,
+    handle: Handle,
+    uri: String,
+    tx: mpsc::Sender<BenchResult<MsgType>>,
+}
+
+impl Sender for Http2Sender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let request = Request::post(&self.uri)
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .body(Full::new(Bytes::from(msg)))
+            .map_err(BenchError::send)?;
+
+        let mut sender = self.sender.clone();
+        let tx = self.tx.clone();
+        self.handle.spawn(async move {
+            let result = async {
+                let response = sender.send_request(request).await?;
+                if !response.status().is_success() {
+                    return Err(anyhow!("Unexpected status {}", response.status()));
+                }
+                let body = response.into_body().collect().await?.to_bytes();
+                return Ok(body.to_vec());
+            }.await;
+            let _ = tx.send(result.map_err(BenchError::receive));
+        });
+        return Ok(());
+    }
+}
+
+/// Hands the responses collected by the sender to the benchmarker.
+struct ChannelReceiver {
+    rx: mpsc::Receiver<BenchResult<MsgType>>,
+}
+
+impl Receiver for ChannelReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(result) => result.map(Some),
+            // The sender holds a handle as well, so this only times out
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+fn run_bench(config: &HttpConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    match config.mode {
+        HttpMode::KeepAlive | HttpMode::NewConnection => {
+            let keep_alive = config.mode == HttpMode::KeepAlive;
+            // Fail early if the server is not reachable
+            let connection = Connection::open(&config.address)?;
+
+            let (tx, rx) = mpsc::channel();
+            let send = RequestSender {
+                address: config.address.clone(),
+                path: config.path.clone(),
+                keep_alive,
+                connection: keep_alive.then_some(connection),
+                response_timeout: Duration::from_secs_f64(config.schedule.drain.grace_secs),
+                tx,
+            };
+            return bench.run(send, ChannelReceiver { rx });
+        }
+        HttpMode::Pipelined => {
+            let connection = Connection::open(&config.address)?;
+            let stream = connection.stream.try_clone().map_err(BenchError::connect)?;
+
+            let send = PipelineSender {
+                stream,
+                address: config.address.clone(),
+                path: config.path.clone(),
+            };
+            return bench.run(send, PipelineReceiver { connection });
+        }
+        HttpMode::Http2 => {
+            let runtime = Runtime::new().map_err(BenchError::connect)?;
+
+            let sender = runtime.block_on(async {
+                let stream = tokio::net::TcpStream::connect(&config.address).await?;
+                stream.set_nodelay(true)?;
+                let (sender, connection) = http2::handshake(TokioExecutor::new(), TokioIo::new(stream)).await?;
+                tokio::spawn(connection);
+                return anyhow::Ok(sender);
+            }).map_err(BenchError::connect)?;
+
+            let (tx, rx) = mpsc::channel();
+            let send = Http2Sender {
+                sender,
+                handle: runtime.handle().clone(),
+                uri: format!("http://{}{}", config.address, config.path),
+                tx,
+            };
+            return bench.run(send, ChannelReceiver { rx });
+        }
+    }
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.http.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "http-echo",
+            &[&config.http.address],
+            Readiness::Listening(config.http.address.clone()),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.http.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.http,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.http.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
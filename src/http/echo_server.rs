@@ -0,0 +1,78 @@
+use anyhow::Result;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+use tokio::time::timeout;
+
+// Time out regularly to notice the exit request
+const ACCEPT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Answers every POST with its own body.
+async fn echo(request: Request<Incoming>) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    if request.method() != Method::POST {
+        let mut response = Response::new(Full::default());
+        *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        return Ok(response);
+    }
+
+    let body = request.into_body().collect().await?.to_bytes();
+    return Ok(Response::new(Full::new(body)));
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let addr_default = "127.0.0.1:8080".to_string();
+    let addr = args.get(1).unwrap_or(&addr_default);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        let listener = TcpListener::bind(addr).await?;
+
+        // HTTP/1.1 and HTTP/2 with prior knowledge are both served on the same port
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        // Answers to pipelined requests are flushed together
+        builder.http1().pipeline_flush(true);
+
+        println!("Waiting for messages..");
+        while running.load(Ordering::SeqCst) {
+            let (stream, _) = match timeout(ACCEPT_TIMEOUT, listener.accept()).await {
+                Ok(Ok(connection)) => connection,
+                Ok(Err(e)) => {
+                    println!("Accept error: {e}");
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            stream.set_nodelay(true)?;
+
+            let builder = builder.clone();
+            tokio::spawn(async move {
+                let connection = builder.serve_connection(TokioIo::new(stream), service_fn(echo));
+                if let Err(e) = connection.await {
+                    println!("Connection error: {e}");
+                }
+            });
+        }
+
+        return anyhow::Ok(());
+    })?;
+
+    println!("Shutting down");
+    return Ok(());
+}
@@ -0,0 +1,53 @@
+use anyhow::{anyhow, bail, Result};
+
+const MAX_HEADERS: usize = 32;
+
+/// Encodes a POST request carrying `body`, with `close` the server drops the
+/// connection after responding.
+pub fn encode_request(host: &str, path: &str, body: &[u8], close: bool) -> Vec<u8> {
+    let connection = if close { "close" } else { "keep-alive" };
+    let mut request = format!(
+        "POST {path} HTTP/1.1\r\n\
+         Host: {host}\r\n\
+         Content-Type: application/octet-stream\r\n\
+         Content-Length: {}\r\n\
+         Connection: {connection}\r\n\
+         \r\n",
+        body.len(),
+    ).into_bytes();
+
+    request.extend_from_slice(body);
+    return request;
+}
+
+/// Takes the first complete response off the front of `buffer` and returns its body.
+///
+/// Returns `None` while the response is still incomplete, only responses with
+/// a `Content-Length` are supported.
+pub fn decode_response(buffer: &mut Vec<u8>) -> Result<Option<Vec<u8>>> {
+    let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+    let mut response = httparse::Response::new(&mut headers);
+
+    let header_len = match response.parse(buffer)? {
+        httparse::Status::Complete(header_len) => header_len,
+        httparse::Status::Partial => return Ok(None),
+    };
+
+    let code = response.code.unwrap_or_default();
+    if code != 200 {
+        bail!("Unexpected status {code} {}", response.reason.unwrap_or_default());
+    }
+
+    let content_length = response.headers.iter()
+        .find(|header| header.name.eq_ignore_ascii_case("content-length"))
+        .ok_or(anyhow!("Response without Content-Length"))?;
+    let body_len: usize = std::str::from_utf8(content_length.value)?.trim().parse()?;
+
+    if buffer.len() < header_len + body_len {
+        return Ok(None);
+    }
+
+    let body = buffer[header_len..header_len + body_len].to_vec();
+    buffer.drain(..header_len + body_len);
+    return Ok(Some(body));
+}
@@ -0,0 +1,227 @@
+use anyhow::bail;
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{BaseProducer, BaseRecord, DeliveryResult, Producer, ProducerContext};
+use rdkafka::{ClientContext, Message, Offset};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, KafkaConfig};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+/// Joining a consumer group takes a few seconds on a fresh broker.
+const SETUP_TIMEOUT: Duration = Duration::from_secs(30);
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn client_config(config: &KafkaConfig) -> ClientConfig {
+    let mut client_config = ClientConfig::new();
+    client_config.set("bootstrap.servers", &config.address);
+    return client_config;
+}
+
+/// Creates the topic, brokers like Redpanda do not create topics on first use.
+fn create_topic(config: &KafkaConfig) -> anyhow::Result<()> {
+    let admin: AdminClient<DefaultClientContext> = client_config(config).create()?;
+    let topic = NewTopic::new(&config.topic, config.partitions, TopicReplication::Fixed(1));
+    let options = AdminOptions::new()
+        .request_timeout(Some(SETUP_TIMEOUT))
+        .operation_timeout(Some(SETUP_TIMEOUT));
+
+    let results = futures::executor::block_on(admin.create_topics([&topic], &options))?;
+    for result in results {
+        match result {
+            Ok(_) | Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {}
+            Err((topic, code)) => bail!("Creating topic {topic} failed: {code}"),
+        }
+    }
+    return Ok(());
+}
+
+/// Counts the messages the broker did not acknowledge.
+#[derive(Default)]
+struct DeliveryContext {
+    num_failed: AtomicUsize,
+}
+
+impl ClientContext for DeliveryContext {}
+
+impl ProducerContext for DeliveryContext {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult, _: ()) {
+        if result.is_err() {
+            self.num_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+struct KafkaSender {
+    producer: BaseProducer<DeliveryContext>,
+    topic: String,
+}
+
+impl Sender for KafkaSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        loop {
+            match self.producer.send(BaseRecord::<(), _>::to(&self.topic).payload(&msg)) {
+                Ok(()) => break,
+                // The local queue only drains once the broker took earlier batches
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => {
+                    self.producer.poll(Duration::from_millis(10));
+                }
+                Err((e, _)) => return Err(kafka_error(e, BenchError::send)),
+            }
+        }
+
+        // Serves the delivery reports
+        self.producer.poll(Duration::ZERO);
+        return Ok(());
+    }
+}
+
+impl Drop for KafkaSender {
+    fn drop(&mut self) {
+        let _ = self.producer.flush(FLUSH_TIMEOUT);
+
+        let num_failed = self.producer.context().num_failed.load(Ordering::Relaxed);
+        if num_failed > 0 {
+            println!("{num_failed} messages were not acknowledged by the broker");
+        }
+    }
+}
+
+struct KafkaReceiver {
+    consumer: BaseConsumer,
+}
+
+impl KafkaReceiver {
+    /// Waits for the group to assign partitions and skips whatever they already hold.
+    fn wait_for_assignment(&self, topic: &str) -> anyhow::Result<()> {
+        let time_start = Instant::now();
+        let assignment = loop {
+            if time_start.elapsed() > SETUP_TIMEOUT {
+                bail!("No partitions assigned by group within {SETUP_TIMEOUT:?}");
+            }
+
+            // The group is only joined while polling
+            if let Some(Err(e)) = self.consumer.poll(Duration::from_millis(100)) {
+                println!("Consumer error while joining group: {e}");
+            }
+            let assignment = self.consumer.assignment()?;
+            if assignment.count() > 0 {
+                break assignment;
+            }
+        };
+
+        // Messages of earlier steps which were never consumed would count as late
+        for element in assignment.elements() {
+            let (_, high) = self.consumer.fetch_watermarks(topic, element.partition(), SETUP_TIMEOUT)?;
+            self.consumer.seek(topic, element.partition(), Offset::Offset(high), SETUP_TIMEOUT)?;
+        }
+        return Ok(());
+    }
+}
+
+impl Receiver for KafkaReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        match self.consumer.poll(timeout) {
+            Some(Ok(msg)) => Ok(Some(msg.payload().unwrap_or_default().to_vec())),
+            Some(Err(e)) => Err(kafka_error(e, BenchError::receive)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Lost brokers end the step, anything else becomes `other`.
+fn kafka_error(e: KafkaError, other: fn(anyhow::Error) -> BenchError) -> BenchError {
+    match e.rdkafka_error_code() {
+        Some(RDKafkaErrorCode::AllBrokersDown | RDKafkaErrorCode::BrokerTransportFailure) => BenchError::connect(e),
+        _ => other(e.into()),
+    }
+}
+
+fn run_bench(config: &KafkaConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    create_topic(config).map_err(BenchError::connect)?;
+
+    let producer: BaseProducer<DeliveryContext> = client_config(config)
+        .set("acks", &config.acks)
+        .set("linger.ms", config.linger_ms.to_string())
+        .set("batch.size", config.batch_size.to_string())
+        .create_with_context(DeliveryContext::default())
+        .map_err(BenchError::connect)?;
+
+    let consumer: BaseConsumer = client_config(config)
+        .set("group.id", &config.group_id)
+        .set("auto.offset.reset", "latest")
+        .create()
+        .map_err(BenchError::connect)?;
+    consumer.subscribe(&[&config.topic]).map_err(BenchError::connect)?;
+
+    let recv = KafkaReceiver { consumer };
+    recv.wait_for_assignment(&config.topic).map_err(BenchError::connect)?;
+    let send = KafkaSender { producer, topic: config.topic.clone() };
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    return bench.run(send, recv);
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.kafka.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_broker(Readiness::Listening(config.kafka.address.clone())).unwrap();
+        supervisor
+    });
+
+    let schedule = config.kafka.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.kafka,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.kafka.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
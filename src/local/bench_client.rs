@@ -0,0 +1,234 @@
+use anyhow::anyhow;
+use std::io::{prelude::*, ErrorKind};
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::time::{Duration, Instant};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, LocalConfig, LocalTransport};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod ring;
+use ring::Ring;
+
+const MAX_DATAGRAM: usize = 65535;
+
+struct StreamSender {
+    stream: UnixStream,
+}
+
+impl Sender for StreamSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        self.stream.write_all(&msg).map_err(BenchError::send)
+    }
+}
+
+struct StreamReceiver {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    message_size: usize,
+}
+
+impl Receiver for StreamReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        // The stream has no framing, so reads are collected until a full message is buffered
+        let time_start = Instant::now();
+        while self.buffer.len() < self.message_size {
+            let remaining = timeout.saturating_sub(time_start.elapsed());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            self.stream.set_read_timeout(Some(remaining)).map_err(BenchError::receive)?;
+
+            let mut chunk = vec![0u8; self.message_size];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(BenchError::connect(anyhow!("disconnected"))),
+                Ok(msg_size) => self.buffer.extend_from_slice(&chunk[..msg_size]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None);
+                }
+                Err(e) => return Err(BenchError::receive(e)),
+            }
+        }
+
+        let rest = self.buffer.split_off(self.message_size);
+        return Ok(Some(std::mem::replace(&mut self.buffer, rest)));
+    }
+}
+
+struct DatagramSender {
+    socket: UnixDatagram,
+}
+
+impl Sender for DatagramSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        return self.socket.send(&msg).map(|_| ()).map_err(BenchError::send);
+    }
+}
+
+struct DatagramReceiver {
+    socket: UnixDatagram,
+    // The echo server replies to this path, it is removed again once the step is done
+    path: String,
+    buffer: Vec<u8>,
+}
+
+impl Receiver for DatagramReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let timeout = timeout.max(Duration::from_millis(1));
+        self.socket.set_read_timeout(Some(timeout)).map_err(BenchError::receive)?;
+
+        match self.socket.recv(&mut self.buffer) {
+            Ok(len) => Ok(Some(self.buffer[..len].to_vec())),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(BenchError::receive(e)),
+        }
+    }
+}
+
+impl Drop for DatagramReceiver {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+struct ShmSender {
+    ring: Ring,
+}
+
+impl Sender for ShmSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        match self.ring.push(&msg) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(BenchError::send(anyhow!("Request ring full"))),
+            Err(e) => Err(BenchError::send(e)),
+        }
+    }
+}
+
+struct ShmReceiver {
+    ring: Ring,
+}
+
+impl Receiver for ShmReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        // Polling keeps the latency down, there is no cross-process wakeup to wait on
+        let time_start = Instant::now();
+        loop {
+            if let Some(msg) = self.ring.pop() {
+                return Ok(Some(msg));
+            }
+
+            if time_start.elapsed() >= timeout {
+                return Ok(None);
+            }
+            std::thread::yield_now();
+        }
+    }
+}
+
+fn run_bench(config: &LocalConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    match config.transport {
+        LocalTransport::Stream => {
+            let stream = UnixStream::connect(&config.path).map_err(BenchError::connect)?;
+            let send = StreamSender {
+                stream: stream.try_clone().map_err(BenchError::connect)?,
+            };
+            let recv = StreamReceiver {
+                stream,
+                buffer: Vec::with_capacity(message_size),
+                message_size,
+            };
+            return bench.run(send, recv);
+        }
+        LocalTransport::Datagram => {
+            let path = format!("{}.{}", config.path, std::process::id());
+            let _ = std::fs::remove_file(&path);
+            let socket = UnixDatagram::bind(&path).map_err(BenchError::connect)?;
+            let recv = DatagramReceiver {
+                socket: socket.try_clone().map_err(BenchError::connect)?,
+                path,
+                buffer: vec![0; MAX_DATAGRAM],
+            };
+
+            socket.connect(&config.path).map_err(BenchError::connect)?;
+            let send = DatagramSender { socket };
+            return bench.run(send, recv);
+        }
+        LocalTransport::Shm => {
+            let (request, response) = ring::open(&config.path).map_err(BenchError::connect)?;
+            // Late responses of the previous step would be taken for this one
+            response.discard();
+
+            let send = ShmSender { ring: request };
+            let recv = ShmReceiver { ring: response };
+            return bench.run(send, recv);
+        }
+    }
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.local.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "local-echo",
+            &[
+                config.local.transport.name(),
+                &config.local.path,
+                &config.local.ring_slots.to_string(),
+                &config.local.ring_slot_size.to_string(),
+            ],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.local.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.local,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.local.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::io::{prelude::*, ErrorKind};
+use std::os::unix::net::{UnixDatagram, UnixListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::Duration;
+
+mod ring;
+
+const SHM_SPIN_LIMIT: u32 = 1000;
+const SHM_IDLE_SLEEP: Duration = Duration::from_micros(50);
+
+fn echo_stream(path: &str, running: &AtomicBool) {
+    let listener = UnixListener::bind(path).unwrap();
+    // Accept without blocking to notice the exit request
+    listener.set_nonblocking(true).unwrap();
+
+    println!("Waiting for messages..");
+    while running.load(Ordering::SeqCst) {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            Err(e) => {
+                println!("Accept error: {e}");
+                continue;
+            }
+        };
+        stream.set_nonblocking(false).unwrap();
+
+        spawn(move || {
+            let mut msg = vec![0u8; 65535];
+            loop {
+                let Ok(msg_size) = stream.read(&mut msg) else {
+                    break;
+                };
+
+                if msg_size == 0 {
+                    // The connection is closed
+                    break;
+                }
+
+                if stream.write_all(&msg[..msg_size]).is_err() {
+                    break;
+                }
+            }
+
+            println!("disconnected");
+        });
+    }
+}
+
+fn echo_datagram(path: &str, running: &AtomicBool) {
+    let socket = UnixDatagram::bind(path).unwrap();
+    // Time out regularly to notice the exit request
+    socket.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+
+    println!("Waiting for messages..");
+    let mut buffer = vec![0u8; 65535];
+    while running.load(Ordering::SeqCst) {
+        let (len, peer) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+            Err(e) => {
+                println!("Receive error: {e}");
+                continue;
+            }
+        };
+
+        // Unnamed senders can't be answered
+        let Some(peer) = peer.as_pathname() else {
+            continue;
+        };
+
+        if let Err(e) = socket.send_to(&buffer[..len], peer) {
+            println!("Send error: {e}");
+        }
+    }
+}
+
+fn echo_shm(path: &str, slots: usize, slot_size: usize, running: &AtomicBool) {
+    let (request, response) = ring::create(path, slots, slot_size).unwrap();
+
+    println!("Waiting for messages..");
+    let mut num_idle = 0;
+    while running.load(Ordering::SeqCst) {
+        let Some(msg) = request.pop() else {
+            // Spin briefly for low latency, then back off instead of burning a core while idle
+            num_idle += 1;
+            if num_idle < SHM_SPIN_LIMIT {
+                std::thread::yield_now();
+            } else {
+                std::thread::sleep(SHM_IDLE_SLEEP);
+            }
+            continue;
+        };
+        num_idle = 0;
+
+        // Wait for the bench to make room instead of dropping the echo
+        while !response.push(&msg).unwrap() {
+            if !running.load(Ordering::SeqCst) {
+                return;
+            }
+            std::thread::yield_now();
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let transport = args.get(1).map(String::as_str).unwrap_or("stream");
+    let path_default = "/tmp/iot-bench.sock".to_string();
+    let path = args.get(2).unwrap_or(&path_default).to_string();
+    let slots = args.get(3).and_then(|slots| slots.parse().ok()).unwrap_or(1024);
+    let slot_size = args.get(4).and_then(|size| size.parse().ok()).unwrap_or(4096);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    // Leftovers of a previous run would make the bind fail
+    let _ = std::fs::remove_file(&path);
+
+    match transport {
+        "stream" => echo_stream(&path, &running),
+        "datagram" => echo_datagram(&path, &running),
+        "shm" => echo_shm(&path, slots, slot_size, &running),
+        _ => panic!("Unknown transport {transport}, expected stream, datagram or shm"),
+    }
+
+    let _ = std::fs::remove_file(&path);
+    println!("Shutting down");
+}
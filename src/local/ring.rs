@@ -0,0 +1,190 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Result};
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+// Head and tail live on separate cache lines, so producer and consumer don't contend
+const HEADER_SIZE: usize = 128;
+const TAIL_OFFSET: usize = 64;
+const LEN_SIZE: usize = 4;
+/// Slots are padded to this, so the heads and tails of the response ring stay aligned.
+const SLOT_ALIGN: usize = 8;
+
+/// A file mapped into memory, shared between the bench and the echo process.
+///
+/// The file starts with a header holding the ring geometry, followed by the
+/// request ring and the response ring.
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// The mapping is only accessed through the atomics and slots of the rings
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn map(file: &File, len: usize) -> Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+        return Ok(Self { ptr: ptr as *mut u8, len });
+    }
+
+    fn word(&self, offset: usize) -> &AtomicU64 {
+        assert!(offset + 8 <= self.len);
+        debug_assert!((self.ptr as usize + offset).is_multiple_of(std::mem::align_of::<AtomicU64>()));
+        return unsafe { &*(self.ptr.add(offset) as *const AtomicU64) };
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+/// One direction of a single-producer single-consumer ring buffer in shared memory.
+pub struct Ring {
+    mapping: Arc<Mapping>,
+    offset: usize,
+    slots: usize,
+    slot_size: usize,
+}
+
+impl Ring {
+    fn head(&self) -> &AtomicU64 {
+        return self.mapping.word(self.offset);
+    }
+
+    fn tail(&self) -> &AtomicU64 {
+        return self.mapping.word(self.offset + TAIL_OFFSET);
+    }
+
+    fn slot(&self, pos: u64) -> *mut u8 {
+        let offset = self.offset + HEADER_SIZE + (pos as usize % self.slots) * self.slot_size;
+        assert!(offset + self.slot_size <= self.mapping.len);
+        return unsafe { self.mapping.ptr.add(offset) };
+    }
+
+    /// Appends a message, returns `false` if the ring is full.
+    pub fn push(&self, data: &[u8]) -> Result<bool> {
+        if data.len() + LEN_SIZE > self.slot_size {
+            return Err(Error::new(ErrorKind::InvalidInput, "Message exceeds the ring slot size"));
+        }
+
+        let head = self.head().load(Ordering::Relaxed);
+        let tail = self.tail().load(Ordering::Acquire);
+        if head - tail >= self.slots as u64 {
+            return Ok(false);
+        }
+
+        unsafe {
+            let slot = self.slot(head);
+            std::ptr::copy_nonoverlapping((data.len() as u32).to_ne_bytes().as_ptr(), slot, LEN_SIZE);
+            std::ptr::copy_nonoverlapping(data.as_ptr(), slot.add(LEN_SIZE), data.len());
+        }
+
+        self.head().store(head + 1, Ordering::Release);
+        return Ok(true);
+    }
+
+    /// Takes the oldest message, if there is one.
+    pub fn pop(&self) -> Option<Vec<u8>> {
+        let tail = self.tail().load(Ordering::Relaxed);
+        let head = self.head().load(Ordering::Acquire);
+        if tail == head {
+            return None;
+        }
+
+        let data = unsafe {
+            let slot = self.slot(tail);
+            let mut len = [0u8; LEN_SIZE];
+            std::ptr::copy_nonoverlapping(slot, len.as_mut_ptr(), LEN_SIZE);
+            let len = (u32::from_ne_bytes(len) as usize).min(self.slot_size - LEN_SIZE);
+            std::slice::from_raw_parts(slot.add(LEN_SIZE), len).to_vec()
+        };
+
+        self.tail().store(tail + 1, Ordering::Release);
+        return Some(data);
+    }
+
+    /// Drops everything that is queued, must only be called by the consumer.
+    pub fn discard(&self) {
+        self.tail().store(self.head().load(Ordering::Acquire), Ordering::Release);
+    }
+}
+
+fn ring_size(slots: usize, slot_size: usize) -> usize {
+    return HEADER_SIZE + slots * slot_size;
+}
+
+fn rings(mapping: Mapping, slots: usize, slot_size: usize) -> (Ring, Ring) {
+    let mapping = Arc::new(mapping);
+    let request = Ring {
+        mapping: mapping.clone(),
+        offset: HEADER_SIZE,
+        slots,
+        slot_size,
+    };
+    let response = Ring {
+        mapping,
+        offset: HEADER_SIZE + ring_size(slots, slot_size),
+        slots,
+        slot_size,
+    };
+    return (request, response);
+}
+
+/// Creates the shared file with empty request and response rings.
+pub fn create(path: &str, slots: usize, slot_size: usize) -> Result<(Ring, Ring)> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+
+    let slot_size = slot_size.next_multiple_of(SLOT_ALIGN);
+    let len = HEADER_SIZE + 2 * ring_size(slots, slot_size);
+    // A freshly extended file is zeroed, so all heads and tails start at 0
+    file.set_len(len as u64)?;
+
+    let mapping = Mapping::map(&file, len)?;
+    mapping.word(0).store(slots as u64, Ordering::Relaxed);
+    mapping.word(8).store(slot_size as u64, Ordering::Release);
+
+    return Ok(rings(mapping, slots, slot_size));
+}
+
+/// Opens the rings created by the other process.
+pub fn open(path: &str) -> Result<(Ring, Ring)> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len() as usize;
+    if len < HEADER_SIZE {
+        return Err(Error::new(ErrorKind::InvalidData, "Shared memory file too short"));
+    }
+
+    let mapping = Mapping::map(&file, len)?;
+    let slots = mapping.word(0).load(Ordering::Relaxed) as usize;
+    let slot_size = mapping.word(8).load(Ordering::Acquire) as usize;
+    if slots == 0 || !slot_size.is_multiple_of(SLOT_ALIGN) || HEADER_SIZE + 2 * ring_size(slots, slot_size) > len {
+        return Err(Error::new(ErrorKind::InvalidData, "Invalid ring geometry"));
+    }
+
+    return Ok(rings(mapping, slots, slot_size));
+}
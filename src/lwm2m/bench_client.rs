@@ -0,0 +1,346 @@
+use anyhow::anyhow;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure, index_from_message};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, Lwm2mConfig, Lwm2mOperation};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+#[path = "../coap/message.rs"]
+mod message;
+use message::{Message, MessageType, format_code, encode_uint, OPTION_CONTENT_FORMAT, OPTION_LOCATION_PATH};
+use message::{CODE_BAD_REQUEST, CODE_CHANGED, CODE_CREATED, CODE_DELETE, CODE_DELETED, CODE_GET, CODE_NOT_FOUND, CODE_POST, CODE_PUT};
+
+const CONTENT_FORMAT_OPAQUE: u32 = 42;
+const PATH_DATA: &str = "19/0/0";
+const PATH_EXECUTE: &str = "3/0/12";
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(10);
+/// Lifetime of registrations without an `lt` query, as in the LwM2M specification.
+const DEFAULT_LIFETIME: Duration = Duration::from_secs(86400);
+const MAX_DATAGRAM: usize = 65535;
+
+/// The registration of the simulated client, it outlives the steps like a real server's.
+struct Registration {
+    endpoint: String,
+    peer: SocketAddr,
+    location: String,
+    lifetime: Duration,
+    time_updated: Instant,
+    /// Time between updates since the last step.
+    update_intervals: Vec<Duration>,
+}
+
+#[derive(Default)]
+struct Registry {
+    client: Option<Registration>,
+    num_registrations: u32,
+}
+
+type SharedRegistry = Arc<Mutex<Registry>>;
+
+/// Answers registration, update and deregistration requests of the client.
+fn handle_registration(socket: &UdpSocket, registry: &SharedRegistry, request: &Message, peer: SocketAddr) -> anyhow::Result<()> {
+    let mut response = Message::new(MessageType::Acknowledgement, CODE_NOT_FOUND, request.message_id, request.token.clone());
+    if request.mtype != MessageType::Confirmable {
+        response.mtype = MessageType::NonConfirmable;
+    }
+
+    let mut registry = registry.lock().unwrap();
+    let path = request.uri_path();
+    let lifetime = request.uri_query("lt").and_then(|lt| lt.parse().ok()).map(Duration::from_secs);
+
+    match (request.code, path.as_str()) {
+        (CODE_POST, "rd") => {
+            let Some(endpoint) = request.uri_query("ep") else {
+                response.code = CODE_BAD_REQUEST;
+                socket.send_to(&response.encode(), peer)?;
+                return Ok(());
+            };
+
+            registry.num_registrations += 1;
+            let location = format!("rd/{}", registry.num_registrations);
+            println!(
+                "Registered {endpoint} at {peer} as {location}, objects {}",
+                String::from_utf8_lossy(&request.payload),
+            );
+
+            response.code = CODE_CREATED;
+            for segment in location.split('/') {
+                response.add_option(OPTION_LOCATION_PATH, segment.as_bytes().to_vec());
+            }
+            registry.client = Some(Registration {
+                endpoint,
+                peer,
+                location,
+                lifetime: lifetime.unwrap_or(DEFAULT_LIFETIME),
+                time_updated: Instant::now(),
+                update_intervals: Vec::new(),
+            });
+        }
+        (CODE_POST | CODE_DELETE, _) => {
+            if let Some(client) = registry.client.as_mut().filter(|client| client.location == path) {
+                if request.code == CODE_DELETE {
+                    println!("Deregistered {}", client.endpoint);
+                    registry.client = None;
+                    response.code = CODE_DELETED;
+                } else {
+                    client.update_intervals.push(client.time_updated.elapsed());
+                    client.time_updated = Instant::now();
+                    // The client may have moved to another address in the meantime
+                    client.peer = peer;
+                    client.lifetime = lifetime.unwrap_or(client.lifetime);
+                    response.code = CODE_CHANGED;
+                }
+            }
+        }
+        _ => {}
+    }
+
+    socket.send_to(&response.encode(), peer)?;
+    return Ok(());
+}
+
+/// Returns the client's address, as long as its registration did not expire.
+fn registered_peer(registry: &SharedRegistry) -> anyhow::Result<SocketAddr> {
+    let mut registry = registry.lock().unwrap();
+    let Some(client) = registry.client.as_ref() else {
+        return Err(anyhow!("No client registered"));
+    };
+    if client.time_updated.elapsed() > client.lifetime {
+        let endpoint = client.endpoint.clone();
+        registry.client = None;
+        return Err(anyhow!("Registration of {endpoint} expired"));
+    }
+    return Ok(client.peer);
+}
+
+struct Lwm2mSender {
+    socket: UdpSocket,
+    registry: SharedRegistry,
+    operations: Vec<Lwm2mOperation>,
+}
+
+impl Sender for Lwm2mSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let peer = registered_peer(&self.registry).map_err(BenchError::connect)?;
+
+        // The message number is the token and its lower bits the message id,
+        // like in the CoAP benchmark
+        let idx = index_from_message(msg.clone()).map_err(BenchError::protocol)?;
+        let operation = self.operations[idx % self.operations.len()];
+
+        // Lost requests are not retransmitted, they count as lost
+        let mut request = Message::new(MessageType::Confirmable, CODE_GET, idx as u16, msg[..8].to_vec());
+        match operation {
+            Lwm2mOperation::Read => {
+                request.set_uri_path(PATH_DATA);
+            }
+            Lwm2mOperation::Write => {
+                request.code = CODE_PUT;
+                request.set_uri_path(PATH_DATA);
+                request.add_option(OPTION_CONTENT_FORMAT, encode_uint(CONTENT_FORMAT_OPAQUE));
+                request.payload = msg;
+            }
+            Lwm2mOperation::Execute => {
+                request.code = CODE_POST;
+                request.set_uri_path(PATH_EXECUTE);
+            }
+        }
+
+        self.socket.send_to(&request.encode(), peer).map_err(BenchError::send)?;
+        return Ok(());
+    }
+}
+
+struct Lwm2mReceiver {
+    socket: UdpSocket,
+    registry: SharedRegistry,
+    operations: Vec<Lwm2mOperation>,
+    /// Error responses per operation and response code.
+    failures: HashMap<(Lwm2mOperation, u8), usize>,
+    buffer: Vec<u8>,
+}
+
+impl Receiver for Lwm2mReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let time_start = Instant::now();
+        loop {
+            let remaining = timeout.saturating_sub(time_start.elapsed());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(remaining)).map_err(BenchError::receive)?;
+
+            let (len, peer) = match self.socket.recv_from(&mut self.buffer) {
+                Ok(received) => received,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Ok(None),
+                Err(e) => return Err(BenchError::receive(e)),
+            };
+            let msg = Message::decode(&self.buffer[..len]).map_err(BenchError::protocol)?;
+
+            // Updates of the registration arrive in between the responses
+            if msg.is_request() {
+                handle_registration(&self.socket, &self.registry, &msg, peer).map_err(BenchError::receive)?;
+                continue;
+            }
+
+            // Error responses still answer the request, they are counted apart
+            if msg.code >> 5 != 2 {
+                let idx = index_from_message(msg.token.clone()).map_err(BenchError::protocol)?;
+                let operation = self.operations[idx % self.operations.len()];
+                *self.failures.entry((operation, msg.code)).or_default() += 1;
+            }
+            // The token is the message number, which is all the benchmark needs
+            return Ok(Some(msg.token));
+        }
+    }
+}
+
+impl Drop for Lwm2mReceiver {
+    fn drop(&mut self) {
+        let mut failures: Vec<_> = self.failures.drain().collect();
+        failures.sort_by_key(|((operation, code), _)| (*operation as u8, *code));
+        for ((operation, code), count) in failures {
+            println!("{operation:?} failed {count} times with {}", format_code(code));
+        }
+
+        let mut registry = self.registry.lock().unwrap();
+        let Some(client) = registry.client.as_mut() else {
+            return;
+        };
+        let intervals = std::mem::take(&mut client.update_intervals);
+        if intervals.is_empty() {
+            return;
+        }
+
+        let mean = intervals.iter().sum::<Duration>() / intervals.len() as u32;
+        println!("{} updated {} times, every {mean:?} on average with lifetime {:?}", client.endpoint, intervals.len(), client.lifetime);
+    }
+}
+
+/// Serves registration requests until the client registered, the registration stays across steps.
+fn wait_for_registration(socket: &UdpSocket, registry: &SharedRegistry) -> BenchResult<()> {
+    if registered_peer(registry).is_ok() {
+        return Ok(());
+    }
+
+    let time_start = Instant::now();
+    socket.set_read_timeout(Some(Duration::from_millis(100))).map_err(BenchError::connect)?;
+    let mut buffer = vec![0u8; MAX_DATAGRAM];
+    while time_start.elapsed() < REGISTRATION_TIMEOUT && !is_interrupted() {
+        let (len, peer) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(BenchError::connect(e)),
+        };
+
+        // Responses to earlier steps are of no interest anymore
+        let Ok(request) = Message::decode(&buffer[..len]) else {
+            continue;
+        };
+        if !request.is_request() {
+            continue;
+        }
+
+        handle_registration(socket, registry, &request, peer).map_err(BenchError::connect)?;
+        if registered_peer(registry).is_ok() {
+            println!("Client registered after {:?}", time_start.elapsed());
+            return Ok(());
+        }
+    }
+
+    return Err(BenchError::timeout(anyhow!("No client registered within {REGISTRATION_TIMEOUT:?}")));
+}
+
+fn run_bench(config: &Lwm2mConfig, socket: &UdpSocket, registry: &SharedRegistry, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    if config.operations.is_empty() {
+        return Err(BenchError::protocol(anyhow!("No operations configured")));
+    }
+    wait_for_registration(socket, registry)?;
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let send = Lwm2mSender {
+        socket: socket.try_clone().map_err(BenchError::connect)?,
+        registry: registry.clone(),
+        operations: config.operations.clone(),
+    };
+    let recv = Lwm2mReceiver {
+        socket: socket.try_clone().map_err(BenchError::connect)?,
+        registry: registry.clone(),
+        operations: config.operations.clone(),
+        failures: HashMap::new(),
+        buffer: vec![0u8; MAX_DATAGRAM],
+    };
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    return bench.run(send, recv);
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    // The server has to listen before the client tries to register
+    let socket = UdpSocket::bind(&config.lwm2m.address).unwrap();
+    let registry = SharedRegistry::default();
+
+    let _supervisor = config.lwm2m.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "lwm2m-client",
+            &[&config.lwm2m.address, &config.lwm2m.endpoint, &config.lwm2m.lifetime_secs.to_string()],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.lwm2m.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.lwm2m,
+            &socket,
+            &registry,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.lwm2m.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[path = "../coap/message.rs"]
+mod message;
+use message::{Message, MessageType, format_code, OPTION_CONTENT_FORMAT, OPTION_URI_QUERY};
+use message::{CODE_CHANGED, CODE_CONTENT, CODE_CREATED, CODE_DELETE, CODE_GET, CODE_METHOD_NOT_ALLOWED, CODE_NOT_FOUND, CODE_POST, CODE_PUT};
+
+const CONTENT_FORMAT_LINK: u32 = 40;
+const CONTENT_FORMAT_OPAQUE: u32 = 42;
+const TOKEN_REGISTER: &[u8] = b"reg";
+const TOKEN_UPDATE: &[u8] = b"upd";
+/// How long the server gets to answer a registration or update.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+const REGISTER_RETRY: Duration = Duration::from_millis(500);
+
+/// The resources of the simulated device, addressed as `object/instance/resource`.
+struct Objects {
+    /// Readable resources, those in `writable` can be written as well.
+    values: HashMap<&'static str, Vec<u8>>,
+    writable: Vec<&'static str>,
+    executable: Vec<&'static str>,
+    num_executed: usize,
+}
+
+impl Objects {
+    fn new() -> Self {
+        let values = HashMap::from([
+            // Device: manufacturer and model number
+            ("3/0/0", b"iot-bench".to_vec()),
+            ("3/0/1", b"lwm2m-client".to_vec()),
+            // Binary app data container: data
+            ("19/0/0", Vec::new()),
+        ]);
+
+        Self {
+            values,
+            writable: vec!["19/0/0"],
+            // Device: reset error code
+            executable: vec!["3/0/12"],
+            num_executed: 0,
+        }
+    }
+
+    /// The object instances announced in the registration.
+    fn links(&self) -> String {
+        return "</1/0>,</3/0>,</19/0>".to_string();
+    }
+
+    /// Answers a request of the server, piggybacked on the acknowledgement.
+    fn handle(&mut self, request: &Message) -> Message {
+        let path = request.uri_path();
+        let path = path.as_str();
+        let mtype = match request.mtype {
+            MessageType::Confirmable => MessageType::Acknowledgement,
+            _ => MessageType::NonConfirmable,
+        };
+        let response = |code: u8| Message::new(mtype, code, request.message_id, request.token.clone());
+
+        match request.code {
+            CODE_GET => {
+                let Some(value) = self.values.get(path) else {
+                    let code = if self.executable.contains(&path) { CODE_METHOD_NOT_ALLOWED } else { CODE_NOT_FOUND };
+                    return response(code);
+                };
+                let mut read = response(CODE_CONTENT);
+                read.add_option(OPTION_CONTENT_FORMAT, message::encode_uint(CONTENT_FORMAT_OPAQUE));
+                read.payload = value.clone();
+                return read;
+            }
+            CODE_PUT => {
+                if !self.writable.contains(&path) {
+                    let code = if self.values.contains_key(path) { CODE_METHOD_NOT_ALLOWED } else { CODE_NOT_FOUND };
+                    return response(code);
+                }
+                if let Some(value) = self.values.get_mut(path) {
+                    *value = request.payload.clone();
+                }
+                return response(CODE_CHANGED);
+            }
+            CODE_POST => {
+                if !self.executable.contains(&path) {
+                    let code = if self.values.contains_key(path) { CODE_METHOD_NOT_ALLOWED } else { CODE_NOT_FOUND };
+                    return response(code);
+                }
+                self.num_executed += 1;
+                return response(CODE_CHANGED);
+            }
+            _ => return response(CODE_METHOD_NOT_ALLOWED),
+        }
+    }
+}
+
+/// A registration or update which was not answered yet.
+struct Pending {
+    token: &'static [u8],
+    message_id: u16,
+    time_sent: Instant,
+}
+
+struct Client {
+    socket: UdpSocket,
+    endpoint: String,
+    lifetime: Duration,
+    objects: Objects,
+    /// Location of the registration at the server, e.g. `rd/1`.
+    location: Option<String>,
+    pending: Option<Pending>,
+    next_update: Instant,
+    message_id: u16,
+}
+
+impl Client {
+    fn next_message_id(&mut self) -> u16 {
+        self.message_id = self.message_id.wrapping_add(1);
+        return self.message_id;
+    }
+
+    fn send_register(&mut self) {
+        let message_id = self.next_message_id();
+        let mut register = Message::new(MessageType::Confirmable, CODE_POST, message_id, TOKEN_REGISTER.to_vec());
+        register.set_uri_path("rd");
+        register.add_option(OPTION_CONTENT_FORMAT, message::encode_uint(CONTENT_FORMAT_LINK));
+        for query in [format!("ep={}", self.endpoint), format!("lt={}", self.lifetime.as_secs()), "lwm2m=1.1".to_string(), "b=U".to_string()] {
+            register.add_option(OPTION_URI_QUERY, query.into_bytes());
+        }
+        register.payload = self.objects.links().into_bytes();
+
+        // The server might not be up yet, which the retries take care of
+        self.pending = Some(Pending { token: TOKEN_REGISTER, message_id, time_sent: Instant::now() });
+        let _ = self.socket.send(&register.encode());
+    }
+
+    fn send_update(&mut self, location: &str) {
+        let message_id = self.next_message_id();
+        let mut update = Message::new(MessageType::Confirmable, CODE_POST, message_id, TOKEN_UPDATE.to_vec());
+        update.set_uri_path(location);
+
+        self.pending = Some(Pending { token: TOKEN_UPDATE, message_id, time_sent: Instant::now() });
+        let _ = self.socket.send(&update.encode());
+    }
+
+    /// Registers, updates halfway through the lifetime and registers again
+    /// whenever the server does not know the registration anymore.
+    fn maintain_registration(&mut self) {
+        if let Some(pending) = &self.pending {
+            if pending.time_sent.elapsed() < RESPONSE_TIMEOUT {
+                return;
+            }
+            println!("No answer from server, registering again");
+            self.pending = None;
+            self.location = None;
+        }
+
+        match self.location.clone() {
+            None => self.send_register(),
+            Some(location) if Instant::now() >= self.next_update => self.send_update(&location),
+            Some(_) => {}
+        }
+    }
+
+    fn handle_response(&mut self, response: &Message) {
+        // Late answers to requests which timed out are ignored
+        let is_answer = |pending: &mut Pending| pending.token == response.token && pending.message_id == response.message_id;
+        let Some(pending) = self.pending.take_if(is_answer) else {
+            return;
+        };
+        let elapsed = pending.time_sent.elapsed();
+
+        match (pending.token, response.code) {
+            (TOKEN_REGISTER, CODE_CREATED) => {
+                let location = response.location_path();
+                println!("Registered as {location} in {elapsed:?}");
+                self.location = Some(location);
+                self.next_update = Instant::now() + self.lifetime / 2;
+            }
+            (TOKEN_UPDATE, CODE_CHANGED) => {
+                println!("Updated registration in {elapsed:?}");
+                self.next_update = Instant::now() + self.lifetime / 2;
+            }
+            (_, code) => {
+                println!("Registration rejected with {}", format_code(code));
+                self.location = None;
+                // Avoid flooding a server which rejects the registration
+                std::thread::sleep(REGISTER_RETRY);
+            }
+        }
+    }
+
+    fn deregister(&mut self) {
+        let Some(location) = self.location.take() else {
+            return;
+        };
+        let message_id = self.next_message_id();
+        let mut deregister = Message::new(MessageType::NonConfirmable, CODE_DELETE, message_id, Vec::new());
+        deregister.set_uri_path(&location);
+        let _ = self.socket.send(&deregister.encode());
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let server_default = "127.0.0.1:5783".to_string();
+    let server = args.get(1).unwrap_or(&server_default);
+    let endpoint = args.get(2).cloned().unwrap_or("iot-bench".to_string());
+    let lifetime_secs: u64 = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(30);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+    socket.connect(server).unwrap();
+    // Time out regularly to keep the registration alive and notice the exit request
+    socket.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+
+    let mut client = Client {
+        socket: socket.try_clone().unwrap(),
+        endpoint,
+        lifetime: Duration::from_secs(lifetime_secs.max(1)),
+        objects: Objects::new(),
+        location: None,
+        pending: None,
+        next_update: Instant::now(),
+        message_id: 0,
+    };
+
+    println!("Waiting for messages..");
+    let mut buffer = vec![0u8; 65535];
+    while running.load(Ordering::SeqCst) {
+        client.maintain_registration();
+
+        let len = match socket.recv(&mut buffer) {
+            Ok(len) => len,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+            // The server is gone, so the next attempt registers again
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                client.location = None;
+                client.pending = None;
+                std::thread::sleep(REGISTER_RETRY);
+                continue;
+            }
+            Err(e) => {
+                println!("Receive error: {e}");
+                continue;
+            }
+        };
+
+        let msg = match Message::decode(&buffer[..len]) {
+            Ok(msg) => msg,
+            Err(e) => {
+                println!("Invalid message: {e}");
+                continue;
+            }
+        };
+
+        if msg.is_request() {
+            let response = client.objects.handle(&msg);
+            if let Err(e) = socket.send(&response.encode()) {
+                println!("Send error: {e}");
+            }
+        } else {
+            client.handle_response(&msg);
+        }
+    }
+
+    client.deregister();
+    println!("Executed {} times", client.objects.num_executed);
+    println!("Shutting down");
+}
@@ -0,0 +1,218 @@
+use anyhow::{anyhow, bail};
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, ModbusConfig};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod frame;
+use frame::{Frame, READ_INPUT_REGISTERS, WRITE_MULTIPLE_REGISTERS, MAX_WRITE_REGISTERS};
+
+/// A master connection issuing one request at a time, reconnecting after failures.
+struct Master {
+    address: String,
+    unit_id: u8,
+    response_timeout: Duration,
+    stream: Option<TcpStream>,
+    transaction_id: u16,
+}
+
+impl Master {
+    fn new(config: &ModbusConfig) -> Self {
+        Self {
+            address: config.address.clone(),
+            unit_id: config.unit_id,
+            response_timeout: Duration::from_secs_f64(config.schedule.drain.grace_secs),
+            stream: None,
+            transaction_id: 0,
+        }
+    }
+
+    fn exchange(stream: &mut TcpStream, request: &Frame) -> anyhow::Result<Vec<u8>> {
+        stream.write_all(&request.encode())?;
+
+        // Responses to requests which timed out earlier are skipped
+        loop {
+            let response = Frame::read_from(stream)?;
+            if response.transaction_id != request.transaction_id {
+                continue;
+            }
+            if response.is_exception() {
+                bail!("Exception {:?} for function {:#04x}", response.data.first(), request.function);
+            }
+            return Ok(response.data);
+        }
+    }
+
+    fn request(&mut self, function: u8, data: Vec<u8>, other: fn(anyhow::Error) -> BenchError) -> BenchResult<Vec<u8>> {
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => {
+                let stream = TcpStream::connect(&self.address).map_err(BenchError::connect)?;
+                stream.set_nodelay(true).map_err(BenchError::connect)?;
+                stream.set_read_timeout(Some(self.response_timeout)).map_err(BenchError::connect)?;
+                self.stream.insert(stream)
+            }
+        };
+
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let request = Frame::new(self.transaction_id, self.unit_id, function, data);
+
+        return Self::exchange(stream, &request).map_err(|e| {
+            // A partly read response would garble the following ones
+            if e.downcast_ref::<std::io::Error>().is_some() {
+                self.stream = None;
+            }
+            other(e)
+        });
+    }
+}
+
+/// Writes every message into the holding registers.
+struct ModbusSender {
+    master: Master,
+    register_address: u16,
+}
+
+impl Sender for ModbusSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let data = frame::write_registers_data(self.register_address, &frame::to_registers(&msg));
+        self.master.request(WRITE_MULTIPLE_REGISTERS, data, BenchError::send)?;
+        return Ok(());
+    }
+}
+
+/// Polls the input registers and reports their content whenever it changed.
+struct ModbusReceiver {
+    master: Master,
+    register_address: u16,
+    message_size: usize,
+    poll_interval: Duration,
+    last: Option<MsgType>,
+    num_polls: usize,
+    time_start: Instant,
+}
+
+impl ModbusReceiver {
+    fn poll(&mut self) -> BenchResult<MsgType> {
+        let count = self.message_size.div_ceil(2) as u16;
+        let data = frame::read_registers_data(self.register_address, count);
+        let response = self.master.request(READ_INPUT_REGISTERS, data, BenchError::receive)?;
+        self.num_polls += 1;
+
+        let registers = frame::registers_from_response(&response).map_err(BenchError::protocol)?;
+        return Ok(frame::from_registers(&registers, self.message_size));
+    }
+}
+
+impl Receiver for ModbusReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let time_start = Instant::now();
+        loop {
+            let msg = self.poll()?;
+            if self.last.as_ref() != Some(&msg) {
+                self.last = Some(msg.clone());
+                return Ok(Some(msg));
+            }
+
+            if time_start.elapsed() + self.poll_interval >= timeout {
+                return Ok(None);
+            }
+            std::thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+impl Drop for ModbusReceiver {
+    fn drop(&mut self) {
+        let elapsed = self.time_start.elapsed().as_secs_f64();
+        println!("Polled {} times, {:.0} polls/s", self.num_polls, self.num_polls as f64 / elapsed);
+    }
+}
+
+fn run_bench(config: &ModbusConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+    if message_size.div_ceil(2) > MAX_WRITE_REGISTERS {
+        return Err(BenchError::protocol(anyhow!("Messages of {message_size} bytes exceed {MAX_WRITE_REGISTERS} registers")));
+    }
+
+    let send = ModbusSender {
+        master: Master::new(config),
+        register_address: config.register_address,
+    };
+    let mut recv = ModbusReceiver {
+        master: Master::new(config),
+        register_address: config.register_address,
+        message_size,
+        poll_interval: Duration::from_millis(config.poll_interval_ms),
+        last: None,
+        num_polls: 0,
+        time_start: Instant::now(),
+    };
+    // Whatever the registers hold from earlier runs is not a new message
+    recv.last = Some(recv.poll()?);
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    return bench.run(send, recv);
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.modbus.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "modbus-echo",
+            &[&config.modbus.address],
+            Readiness::Listening(config.modbus.address.clone()),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.modbus.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.modbus,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.modbus.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::Result;
+use std::io::{prelude::*, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::spawn;
+use std::time::Duration;
+
+mod frame;
+use frame::{Frame, ILLEGAL_DATA_ADDRESS, ILLEGAL_DATA_VALUE, ILLEGAL_FUNCTION};
+use frame::{READ_HOLDING_REGISTERS, READ_INPUT_REGISTERS, WRITE_MULTIPLE_REGISTERS, WRITE_SINGLE_REGISTER};
+use frame::{MAX_READ_REGISTERS, MAX_WRITE_REGISTERS};
+
+const NUM_REGISTERS: usize = 65536;
+
+/// The slave's register table, the input registers mirror the holding registers
+/// so whatever the master writes can be polled back.
+type Registers = Arc<Mutex<Vec<u16>>>;
+
+/// Checks the register range of a request, returning its start and count.
+fn register_range(data: &[u8], max_count: usize) -> Result<(usize, usize), u8> {
+    if data.len() < 4 {
+        return Err(ILLEGAL_DATA_VALUE);
+    }
+    let address = u16::from_be_bytes([data[0], data[1]]) as usize;
+    let count = u16::from_be_bytes([data[2], data[3]]) as usize;
+
+    if count == 0 || count > max_count {
+        return Err(ILLEGAL_DATA_VALUE);
+    }
+    if address + count > NUM_REGISTERS {
+        return Err(ILLEGAL_DATA_ADDRESS);
+    }
+    return Ok((address, count));
+}
+
+fn handle(request: &Frame, registers: &Registers) -> Result<Vec<u8>, u8> {
+    match request.function {
+        READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+            let (address, count) = register_range(&request.data, MAX_READ_REGISTERS)?;
+            let registers = registers.lock().unwrap();
+
+            let mut data = vec![(count * 2) as u8];
+            data.extend(registers[address..address + count].iter().flat_map(|value| value.to_be_bytes()));
+            Ok(data)
+        }
+        WRITE_SINGLE_REGISTER => {
+            let [address_hi, address_lo, value_hi, value_lo] = request.data[..] else {
+                return Err(ILLEGAL_DATA_VALUE);
+            };
+            let address = u16::from_be_bytes([address_hi, address_lo]) as usize;
+            registers.lock().unwrap()[address] = u16::from_be_bytes([value_hi, value_lo]);
+            // The request is echoed as response
+            Ok(request.data.clone())
+        }
+        WRITE_MULTIPLE_REGISTERS => {
+            let (address, count) = register_range(&request.data, MAX_WRITE_REGISTERS)?;
+            let values = request.data.get(5..5 + count * 2).ok_or(ILLEGAL_DATA_VALUE)?;
+            registers.lock().unwrap()[address..address + count].copy_from_slice(&frame::to_registers(values));
+            Ok(request.data[..4].to_vec())
+        }
+        _ => Err(ILLEGAL_FUNCTION),
+    }
+}
+
+fn serve(mut stream: TcpStream, registers: Registers) {
+    loop {
+        let request = match Frame::read_from(&mut stream) {
+            Ok(request) => request,
+            // The connection is closed
+            Err(_) => break,
+        };
+
+        let response = match handle(&request, &registers) {
+            Ok(data) => Frame::new(request.transaction_id, request.unit_id, request.function, data),
+            Err(code) => request.exception(code),
+        };
+        if stream.write_all(&response.encode()).is_err() {
+            break;
+        }
+    }
+
+    println!("disconnected");
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let addr_default = "127.0.0.1:5020".to_string();
+    let addr = args.get(1).unwrap_or(&addr_default);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let registers: Registers = Arc::new(Mutex::new(vec![0; NUM_REGISTERS]));
+
+    let listener = TcpListener::bind(addr)?;
+    // Accept without blocking to notice the exit request
+    listener.set_nonblocking(true)?;
+
+    println!("Waiting for messages..");
+    while running.load(Ordering::SeqCst) {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            Err(e) => {
+                println!("Accept error: {e}");
+                continue;
+            }
+        };
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+
+        let registers = registers.clone();
+        spawn(move || serve(stream, registers));
+    }
+
+    println!("Shutting down");
+    return Ok(());
+}
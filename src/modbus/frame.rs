@@ -0,0 +1,156 @@
+use anyhow::{bail, Result};
+use std::io::prelude::*;
+
+pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+pub const READ_INPUT_REGISTERS: u8 = 0x04;
+pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+pub const ILLEGAL_FUNCTION: u8 = 0x01;
+pub const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+pub const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+const EXCEPTION_FLAG: u8 = 0x80;
+
+/// Registers a single read may return, writes are limited to 123.
+pub const MAX_READ_REGISTERS: usize = 125;
+pub const MAX_WRITE_REGISTERS: usize = 123;
+
+/// A Modbus TCP frame, the MBAP header followed by the PDU.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub transaction_id: u16,
+    pub unit_id: u8,
+    pub function: u8,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(transaction_id: u16, unit_id: u8, function: u8, data: Vec<u8>) -> Self {
+        Self { transaction_id, unit_id, function, data }
+    }
+
+    /// Answers this request with an exception.
+    pub fn exception(&self, code: u8) -> Self {
+        Self::new(self.transaction_id, self.unit_id, self.function | EXCEPTION_FLAG, vec![code])
+    }
+
+    pub fn is_exception(&self) -> bool {
+        self.function & EXCEPTION_FLAG != 0
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(8 + self.data.len());
+        frame.extend_from_slice(&self.transaction_id.to_be_bytes());
+        // Protocol identifier, always 0 for Modbus
+        frame.extend_from_slice(&0u16.to_be_bytes());
+        frame.extend_from_slice(&(self.data.len() as u16 + 2).to_be_bytes());
+        frame.push(self.unit_id);
+        frame.push(self.function);
+        frame.extend_from_slice(&self.data);
+        return frame;
+    }
+
+    pub fn read_from(stream: &mut impl Read) -> Result<Self> {
+        let mut header = [0u8; 8];
+        stream.read_exact(&mut header)?;
+
+        let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+        if header[2..4] != [0, 0] || length < 2 {
+            bail!("Invalid MBAP header {header:?}");
+        }
+
+        let mut data = vec![0u8; length - 2];
+        stream.read_exact(&mut data)?;
+        return Ok(Self::new(u16::from_be_bytes([header[0], header[1]]), header[6], header[7], data));
+    }
+}
+
+/// Packs bytes into big endian registers, an odd byte is padded with zero.
+pub fn to_registers(bytes: &[u8]) -> Vec<u16> {
+    return bytes.chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]))
+        .collect();
+}
+
+pub fn from_registers(registers: &[u16], len: usize) -> Vec<u8> {
+    let mut bytes: Vec<u8> = registers.iter().flat_map(|register| register.to_be_bytes()).collect();
+    bytes.truncate(len);
+    return bytes;
+}
+
+pub fn write_registers_data(address: u16, values: &[u16]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(5 + values.len() * 2);
+    data.extend_from_slice(&address.to_be_bytes());
+    data.extend_from_slice(&(values.len() as u16).to_be_bytes());
+    data.push((values.len() * 2) as u8);
+    data.extend(values.iter().flat_map(|value| value.to_be_bytes()));
+    return data;
+}
+
+pub fn read_registers_data(address: u16, count: u16) -> Vec<u8> {
+    let mut data = Vec::with_capacity(4);
+    data.extend_from_slice(&address.to_be_bytes());
+    data.extend_from_slice(&count.to_be_bytes());
+    return data;
+}
+
+/// Takes the register values out of the response to a read.
+pub fn registers_from_response(data: &[u8]) -> Result<Vec<u16>> {
+    let Some((&byte_count, values)) = data.split_first() else {
+        bail!("Empty read response");
+    };
+    if values.len() != byte_count as usize || !byte_count.is_multiple_of(2) {
+        bail!("Read response with {} bytes announces {byte_count}", values.len());
+    }
+    return Ok(to_registers(values));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let frame = Frame::new(0x0102, 1, WRITE_MULTIPLE_REGISTERS, write_registers_data(7, &[0xabcd, 0x0001]));
+        let buf = frame.encode();
+        assert_eq!(buf[..8], [0x01, 0x02, 0, 0, 0, 11, 1, WRITE_MULTIPLE_REGISTERS]);
+        assert_eq!(buf[8..], [0, 7, 0, 2, 4, 0xab, 0xcd, 0x00, 0x01]);
+
+        let decoded = Frame::read_from(&mut buf.as_slice()).unwrap();
+        assert_eq!(decoded.transaction_id, 0x0102);
+        assert_eq!(decoded.unit_id, 1);
+        assert_eq!(decoded.function, WRITE_MULTIPLE_REGISTERS);
+        assert_eq!(decoded.data, frame.data);
+    }
+
+    #[test]
+    fn exception() {
+        let request = Frame::new(3, 1, READ_HOLDING_REGISTERS, read_registers_data(0, 2));
+        let response = Frame::read_from(&mut request.exception(ILLEGAL_DATA_ADDRESS).encode().as_slice()).unwrap();
+        assert!(response.is_exception());
+        assert_eq!(response.function & !EXCEPTION_FLAG, READ_HOLDING_REGISTERS);
+        assert_eq!(response.data, [ILLEGAL_DATA_ADDRESS]);
+    }
+
+    #[test]
+    fn registers() {
+        assert_eq!(to_registers(&[1, 2, 3]), [0x0102, 0x0300]);
+        assert_eq!(from_registers(&[0x0102, 0x0300], 3), [1, 2, 3]);
+        assert_eq!(registers_from_response(&[4, 0, 1, 0, 2]).unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn invalid_frames() {
+        // Protocol identifier other than 0
+        assert!(Frame::read_from(&mut [0, 1, 0, 1, 0, 2, 1, 3].as_slice()).is_err());
+        // Length below unit id and function
+        assert!(Frame::read_from(&mut [0, 1, 0, 0, 0, 1, 1, 3].as_slice()).is_err());
+        // Truncated data
+        assert!(Frame::read_from(&mut [0, 1, 0, 0, 0, 4, 1, 3, 0].as_slice()).is_err());
+
+        assert!(registers_from_response(&[]).is_err());
+        assert!(registers_from_response(&[4, 0, 1]).is_err());
+        assert!(registers_from_response(&[3, 0, 1, 2]).is_err());
+    }
+}
@@ -1,17 +1,22 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use mqtt::Client;
 use paho_mqtt as mqtt;
-use anyhow::Result;
+use anyhow::anyhow;
 
 #[path="../benchmarker.rs"]
 mod benchmarker;
-use benchmarker::{Receiver, Sender, MsgType, index_from_message, Benchmarker};
+use benchmarker::{Receiver, Sender, MsgType, Benchmarker, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
 
 #[path = "../config.rs"]
 mod config;
 use config::Config;
 
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
 struct MqttSender {
     client: Client,
     topic_send: String,
@@ -24,86 +29,58 @@ impl MqttSender {
 }
 
 impl Sender for MqttSender {
-    fn send(&mut self, msg: MsgType) -> Result<()> {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
         let rsp = mqtt::MessageBuilder::new()
             .topic(&self.topic_send)
             .payload(msg)
             .qos(1)
             .finalize();
 
-        return Ok(self.client.publish(rsp)?)
+        return self.client.publish(rsp).map_err(|e| {
+            if self.client.is_connected() {
+                BenchError::send(e)
+            } else {
+                BenchError::connect(e)
+            }
+        });
     }
 }
 
 impl Drop for MqttSender{
     fn drop(&mut self) {
-        self.client.disconnect(None).unwrap();
+        let _ = self.client.disconnect(None);
     }
 }
 
 
 struct MqttReceiver {
     client: Client,
-    num_messages: usize,
-    duration: Duration,
+    rx: mqtt::Receiver<Option<mqtt::Message>>,
 }
 
 impl MqttReceiver {
-    pub fn new(
-        client: Client,
-        num_messages: usize,
-        duration: Duration,
-    ) -> Self {
+    pub fn new(client: Client) -> Self {
+        let rx = client.start_consuming();
         Self {
             client,
-            num_messages,
-            duration,
+            rx,
         }
     }
 }
 
 impl Receiver for MqttReceiver {
-    fn listen(&mut self) -> Result<Vec<Option<Instant>>> {
-
-        let time_start = Instant::now();
-        let mut timestamps = vec![None; self.num_messages];
-        let mut num_received = 0;
-
-        let rx = self.client.start_consuming();
-
-        println!("Waiting for messages..");
-
-        loop {
-            if time_start.elapsed() > self.duration + Duration::from_secs(5) {
-                break;
-            }
-
-            if num_received >= self.num_messages {
-                break;
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(Some(msg)) => Ok(Some(msg.payload().to_vec())),
+            Ok(None) => {
+                if self.client.is_connected() || try_reconnect(&self.client) {
+                    return Ok(None);
+                }
+                Err(BenchError::connect(anyhow!("Connection to broker lost")))
             }
-
-            let Ok(msg) = rx.try_recv() else {
-                continue;
-            };
-
-
-            let Some(msg) = msg else {
-                if self.client.is_connected() || !try_reconnect(&self.client) {
-                    break;
-                } 
-                continue;
-            };
-
-            let Ok(idx) = index_from_message(msg.payload().to_vec()) else {
-                continue;
-            };
-
-            timestamps[idx] = Some(Instant::now());
-            num_received += 1;
+            Err(e) if e.is_timeout() => Ok(None),
+            Err(e) => Err(BenchError::connect(e)),
         }
-
-        return Ok(timestamps);
-
     }
 }
 
@@ -114,7 +91,7 @@ impl Receiver for MqttReceiver {
 //}
 
 
-fn mqtt_init(addr: &str, topic: &str) -> Client {
+fn mqtt_init(addr: &str, topic: &str) -> BenchResult<Client> {
     let host = format!("mqtt://{addr}");
     println!("Connecting to MQTT broker at {}", host);
 
@@ -124,7 +101,7 @@ fn mqtt_init(addr: &str, topic: &str) -> Client {
         .finalize();
 
     let client = mqtt::Client::new(opts)
-        .expect("Error creating client");
+        .map_err(BenchError::connect)?;
 
 
     let resp_disconnect = mqtt::MessageBuilder::new()
@@ -138,36 +115,32 @@ fn mqtt_init(addr: &str, topic: &str) -> Client {
         .will_message(resp_disconnect)
         .finalize();
 
-    match client.connect(conn_opts) {
-        Ok(rsp) => {
-            if let Some(conn_rsp) = rsp.connect_response() {
-                println!("Connected to broker");
-
-                if conn_rsp.session_present {
-                    println!("Session already present on broker");
-                } else {
-                    println!("Subscribing to topic {}", topic);
-                    client.subscribe(topic, 1)
-                        .and_then(|rsp| {
-                            return rsp.subscribe_response().ok_or(mqtt::Error::General("Bad response"));
-                        })
-                        .and_then(|vqos| {
-                            println!("QoS granted: {:?}", vqos);
-                            return Ok(());
-                        })
-                        .unwrap_or_else(|err| {
-                            client.disconnect(None).unwrap();
-                            panic!("Error subscribing to topic: {:?}", err);
-                        });
+    let rsp = client.connect(conn_opts)
+        .map_err(|e| BenchError::connect(anyhow!("Error connecting to broker: {e}")))?;
+
+    if let Some(conn_rsp) = rsp.connect_response() {
+        println!("Connected to broker");
+
+        if conn_rsp.session_present {
+            println!("Session already present on broker");
+        } else {
+            println!("Subscribing to topic {}", topic);
+            let subscribed = client.subscribe(topic, 1)
+                .and_then(|rsp| {
+                    return rsp.subscribe_response().ok_or(mqtt::Error::General("Bad response"));
+                });
+
+            match subscribed {
+                Ok(vqos) => println!("QoS granted: {:?}", vqos),
+                Err(e) => {
+                    let _ = client.disconnect(None);
+                    return Err(BenchError::protocol(anyhow!("Error subscribing to topic: {e}")));
                 }
             }
         }
-        Err(e) => {
-            panic!("Error connecting to broker {:?}", e);
-        }
     }
 
-    return client;
+    return Ok(client);
 }
 
 fn try_reconnect(client: &mqtt::Client) -> bool {
@@ -183,36 +156,64 @@ fn try_reconnect(client: &mqtt::Client) -> bool {
     return false;
 }
 
-fn run_bench(config: Config, num_messages: usize, duration: Duration) {
+fn run_bench(config: Config, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
     println!("init");
-    let client = mqtt_init(&config.mqtt.address, &config.mqtt.topic_recv);
+    let client = mqtt_init(&config.mqtt.address, &config.mqtt.topic_recv)?;
     let message_size = config.mqtt.message_size%8 + 8;
 
     let send = MqttSender::new(client.clone(), config.mqtt.topic_send);
-    let recv = MqttReceiver::new(client, num_messages, duration);
+    let recv = MqttReceiver::new(client);
     let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.mqtt.schedule);
+    bench.out_file = config.mqtt.out_file.clone();
 
-    let stats = bench.run(send, recv);
-    dbg!(&stats);
+    return bench.run(send, recv);
 }
 
 fn main() {
+    handle_interrupt();
+
     let config: Config = toml::from_str(
         &std::fs::read_to_string("config.toml").unwrap()
     ).unwrap();
 
-    let schedule = config.tcp.schedule.clone();
+    let _supervisor = config.mqtt.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_broker(Readiness::Listening(config.mqtt.address.clone())).unwrap();
+        supervisor.spawn_echo(
+            "mqtt-echo",
+            &[&config.mqtt.address],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.mqtt.schedule.clone();
 
     let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
     for i in 0..schedule.steps {
         let duration = Duration::from_secs(schedule.secs_per_step);
         let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
 
-        run_bench(
+        let result = run_bench(
             config.clone(),
             num_messages.floor() as usize, 
             duration, 
         );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.mqtt.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
     }
 
 }
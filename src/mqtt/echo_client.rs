@@ -96,5 +96,10 @@ fn main() -> Result<()> {
         }
     }
 
+    println!("Shutting down");
+    if client.is_connected() {
+        client.disconnect(None)?;
+    }
+
     return Ok(());
 }
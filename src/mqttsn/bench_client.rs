@@ -0,0 +1,246 @@
+use anyhow::{anyhow, bail};
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure, index_from_message};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, MqttConfig, MqttSnConfig};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod packet;
+use packet::{Packet, FLAG_CLEAN_SESSION, RC_ACCEPTED, TOPIC_NORMAL, TOPIC_PREDEFINED};
+
+const CLIENT_ID: &str = "mqttsn_bench";
+const KEEP_ALIVE_SECS: u16 = 60;
+const SETUP_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_DATAGRAM: usize = 65535;
+
+/// Sends a packet during the setup and waits for the answer accepted by `is_answer`.
+fn request(socket: &UdpSocket, packet: Packet, is_answer: impl Fn(&Packet) -> bool) -> anyhow::Result<Packet> {
+    socket.send(&packet.encode())?;
+
+    let time_start = Instant::now();
+    let mut buffer = vec![0u8; MAX_DATAGRAM];
+    loop {
+        let remaining = SETUP_TIMEOUT.saturating_sub(time_start.elapsed());
+        if remaining.is_zero() {
+            bail!("No answer from gateway to {packet:?}");
+        }
+        socket.set_read_timeout(Some(remaining))?;
+
+        let size = match socket.recv(&mut buffer) {
+            Ok(size) => size,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let answer = Packet::decode(&buffer[..size])?;
+        if is_answer(&answer) {
+            return Ok(answer);
+        }
+    }
+}
+
+/// Connects, subscribes to `topic_recv` and returns the topic ID to publish on.
+fn setup(socket: &UdpSocket, config: &MqttSnConfig, mqtt: &MqttConfig) -> anyhow::Result<u16> {
+    let connect = Packet::Connect {
+        flags: FLAG_CLEAN_SESSION,
+        duration: KEEP_ALIVE_SECS,
+        client_id: CLIENT_ID.to_string(),
+    };
+    match request(socket, connect, |answer| matches!(answer, Packet::Connack { .. }))? {
+        Packet::Connack { return_code: RC_ACCEPTED } => {}
+        answer => bail!("Connection refused: {answer:?}"),
+    }
+
+    let subscribe = Packet::Subscribe {
+        flags: packet::qos_flags(config.qos.max(0)),
+        msg_id: 1,
+        topic_name: mqtt.topic_recv.clone(),
+    };
+    match request(socket, subscribe, |answer| matches!(answer, Packet::Suback { .. }))? {
+        Packet::Suback { return_code: RC_ACCEPTED, .. } => {}
+        answer => bail!("Subscription refused: {answer:?}"),
+    }
+
+    // QoS -1 publishes on a topic ID both sides know beforehand
+    if config.qos < 0 {
+        return Ok(config.predefined_topic_id);
+    }
+
+    let register = Packet::Register { topic_id: 0, msg_id: 2, topic_name: mqtt.topic_send.clone() };
+    match request(socket, register, |answer| matches!(answer, Packet::Regack { .. }))? {
+        Packet::Regack { topic_id, return_code: RC_ACCEPTED, .. } => Ok(topic_id),
+        answer => bail!("Registration refused: {answer:?}"),
+    }
+}
+
+struct MqttSnSender {
+    socket: UdpSocket,
+    topic_id: u16,
+    qos: i8,
+}
+
+impl Sender for MqttSnSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let (topic_type, msg_id) = match self.qos {
+            -1 => (TOPIC_PREDEFINED, 0),
+            0 => (TOPIC_NORMAL, 0),
+            // The message number gives a distinct non-zero id to every pending publish
+            _ => {
+                let idx = index_from_message(msg.clone()).map_err(BenchError::protocol)?;
+                (TOPIC_NORMAL, (idx % 0xffff + 1) as u16)
+            }
+        };
+
+        let publish = Packet::Publish {
+            flags: packet::qos_flags(self.qos) | topic_type,
+            topic_id: self.topic_id,
+            msg_id,
+            data: msg,
+        };
+        self.socket.send(&publish.encode()).map_err(BenchError::send)?;
+        return Ok(());
+    }
+}
+
+struct MqttSnReceiver {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+}
+
+impl Receiver for MqttSnReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let time_start = Instant::now();
+        loop {
+            let remaining = timeout.saturating_sub(time_start.elapsed());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(remaining)).map_err(BenchError::receive)?;
+
+            let size = match self.socket.recv(&mut self.buffer) {
+                Ok(size) => size,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Ok(None),
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => return Err(BenchError::connect(e)),
+                Err(e) => return Err(BenchError::receive(e)),
+            };
+
+            match Packet::decode(&self.buffer[..size]).map_err(BenchError::protocol)? {
+                Packet::Publish { flags, topic_id, msg_id, data } => {
+                    if packet::flags_qos(flags) == 1 {
+                        let puback = Packet::Puback { topic_id, msg_id, return_code: RC_ACCEPTED };
+                        self.socket.send(&puback.encode()).map_err(BenchError::receive)?;
+                    }
+                    return Ok(Some(data));
+                }
+                Packet::Puback { return_code, .. } if return_code != RC_ACCEPTED => {
+                    return Err(BenchError::protocol(anyhow!("Publish rejected with return code {return_code}")));
+                }
+                Packet::Disconnect => return Err(BenchError::connect(anyhow!("Disconnected by gateway"))),
+                // Acks of accepted publishes
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for MqttSnReceiver {
+    fn drop(&mut self) {
+        let _ = self.socket.send(&Packet::Disconnect.encode());
+    }
+}
+
+fn run_bench(config: &MqttSnConfig, mqtt: &MqttConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    if !(-1..=1).contains(&config.qos) {
+        return Err(BenchError::protocol(anyhow!("QoS {} is not supported", config.qos)));
+    }
+
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(BenchError::connect)?;
+    socket.connect(&config.gateway_address).map_err(BenchError::connect)?;
+    let topic_id = setup(&socket, config, mqtt).map_err(BenchError::connect)?;
+
+    // QoS -1 publishes from a socket which never connected to the gateway
+    let send_socket = match config.qos {
+        -1 => {
+            let socket = UdpSocket::bind("0.0.0.0:0").map_err(BenchError::connect)?;
+            socket.connect(&config.gateway_address).map_err(BenchError::connect)?;
+            socket
+        }
+        _ => socket.try_clone().map_err(BenchError::connect)?,
+    };
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let send = MqttSnSender { socket: send_socket, topic_id, qos: config.qos };
+    let recv = MqttSnReceiver { socket, buffer: vec![0u8; MAX_DATAGRAM] };
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    return bench.run(send, recv);
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.mqttsn.spawn.as_ref().map(|spawn| {
+        let predefined = format!("{}={}", config.mqttsn.predefined_topic_id, config.mqtt.topic_send);
+
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_broker(Readiness::Listening(config.mqtt.address.clone())).unwrap();
+        supervisor.spawn_echo(
+            "mqtt-echo",
+            &[&config.mqtt.address],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor.spawn_echo(
+            "mqttsn-gateway",
+            &[&config.mqttsn.gateway_address, &config.mqtt.address, &predefined],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.mqttsn.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.mqttsn,
+            &config.mqtt,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.mqttsn.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::{anyhow, Result};
+use paho_mqtt as mqtt;
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+mod packet;
+use packet::{Packet, RC_ACCEPTED, RC_CONGESTION, RC_INVALID_TOPIC_ID, RC_NOT_SUPPORTED, TOPIC_NORMAL, TOPIC_PREDEFINED};
+
+/// Topic IDs and subscriptions of the MQTT-SN clients.
+#[derive(Default)]
+struct Registry {
+    /// Predefined and registered topic IDs, the same for all clients.
+    topic_ids: HashMap<String, u16>,
+    topics: HashMap<u16, String>,
+    next_topic_id: u16,
+    /// Connected clients with the QoS of their subscriptions by topic.
+    clients: HashMap<SocketAddr, HashMap<String, i8>>,
+    /// Topics the gateway subscribed to at the broker.
+    subscribed: HashSet<String>,
+}
+
+impl Registry {
+    /// The topic's ID, registering it if needed, `None` once all IDs are taken.
+    fn topic_id(&mut self, topic: &str) -> Option<u16> {
+        if let Some(topic_id) = self.topic_ids.get(topic) {
+            return Some(*topic_id);
+        }
+
+        // The reserved 0xFFFF is never handed out, as no ID follows it
+        let topic_id = self.next_topic_id;
+        self.next_topic_id = topic_id.checked_add(1)?;
+        self.topic_ids.insert(topic.to_string(), topic_id);
+        self.topics.insert(topic_id, topic.to_string());
+        return Some(topic_id);
+    }
+}
+
+struct Gateway {
+    socket: UdpSocket,
+    client: mqtt::Client,
+    registry: Mutex<Registry>,
+}
+
+impl Gateway {
+    fn reply(&self, addr: SocketAddr, packet: Packet) {
+        if let Err(e) = self.socket.send_to(&packet.encode(), addr) {
+            println!("Send error: {e}");
+        }
+    }
+
+    fn handle(&self, addr: SocketAddr, packet: Packet) -> Result<()> {
+        match packet {
+            Packet::Connect { client_id, .. } => {
+                println!("Client {client_id} connected from {addr}");
+                self.registry.lock().unwrap().clients.insert(addr, HashMap::new());
+                self.reply(addr, Packet::Connack { return_code: RC_ACCEPTED });
+            }
+            Packet::Register { msg_id, topic_name, .. } => {
+                let regack = match self.registry.lock().unwrap().topic_id(&topic_name) {
+                    Some(topic_id) => Packet::Regack { topic_id, msg_id, return_code: RC_ACCEPTED },
+                    None => Packet::Regack { topic_id: 0, msg_id, return_code: RC_CONGESTION },
+                };
+                self.reply(addr, regack);
+            }
+            Packet::Subscribe { flags, msg_id, topic_name } => {
+                let qos = packet::flags_qos(flags).clamp(0, 1);
+                let flags = packet::qos_flags(qos);
+                let (topic_id, subscribe) = {
+                    let mut registry = self.registry.lock().unwrap();
+                    let Some(topic_id) = registry.topic_id(&topic_name) else {
+                        drop(registry);
+                        self.reply(addr, Packet::Suback { flags, topic_id: 0, msg_id, return_code: RC_CONGESTION });
+                        return Ok(());
+                    };
+                    let subscribe = registry.subscribed.insert(topic_name.clone());
+                    if let Some(subscriptions) = registry.clients.get_mut(&addr) {
+                        subscriptions.insert(topic_name.clone(), qos);
+                    }
+                    (topic_id, subscribe)
+                };
+
+                if subscribe {
+                    self.client.subscribe(&topic_name, 1)?;
+                }
+                self.reply(addr, Packet::Suback { flags, topic_id, msg_id, return_code: RC_ACCEPTED });
+            }
+            Packet::Publish { flags, topic_id, msg_id, data } => {
+                let qos = packet::flags_qos(flags);
+                let topic = match packet::topic_type(flags) {
+                    TOPIC_NORMAL | TOPIC_PREDEFINED => self.registry.lock().unwrap().topics.get(&topic_id).cloned(),
+                    _ => None,
+                };
+
+                // QoS -1 needs no connection, everything else only from connected clients
+                let connected = self.registry.lock().unwrap().clients.contains_key(&addr);
+                let return_code = match topic {
+                    _ if qos == 2 => RC_NOT_SUPPORTED,
+                    Some(_) if qos >= 0 && !connected => RC_NOT_SUPPORTED,
+                    Some(topic) => {
+                        let msg = mqtt::MessageBuilder::new()
+                            .topic(topic)
+                            .payload(data)
+                            .qos(qos.max(0) as i32)
+                            .finalize();
+                        self.client.publish(msg)?;
+                        RC_ACCEPTED
+                    }
+                    None => RC_INVALID_TOPIC_ID,
+                };
+
+                if qos == 1 || (return_code != RC_ACCEPTED && qos >= 0) {
+                    self.reply(addr, Packet::Puback { topic_id, msg_id, return_code });
+                }
+            }
+            Packet::Pingreq => self.reply(addr, Packet::Pingresp),
+            Packet::Disconnect => {
+                self.registry.lock().unwrap().clients.remove(&addr);
+                self.reply(addr, Packet::Disconnect);
+            }
+            // Deliveries to the clients are sent once, so their acks need no handling
+            Packet::Puback { .. } => {}
+            packet => println!("Unexpected packet from {addr}: {packet:?}"),
+        }
+        return Ok(());
+    }
+
+    /// Forwards a message from the broker to every client subscribed to its topic.
+    fn forward(&self, msg: &mqtt::Message, msg_id: u16) {
+        let registry = self.registry.lock().unwrap();
+        let Some(topic_id) = registry.topic_ids.get(msg.topic()).copied() else {
+            return;
+        };
+
+        for (addr, subscriptions) in &registry.clients {
+            let Some(qos) = subscriptions.get(msg.topic()) else {
+                continue;
+            };
+            let flags = packet::qos_flags((*qos).min(msg.qos() as i8)) | TOPIC_NORMAL;
+            let data = msg.payload().to_vec();
+            self.reply(*addr, Packet::Publish { flags, topic_id, msg_id, data });
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |idx: usize, default: &str| args.get(idx).cloned().unwrap_or(default.to_string());
+
+    let addr = arg(1, "127.0.0.1:1884");
+    let broker_addr = arg(2, "localhost:1883");
+
+    // Predefined topics as `id=topic`
+    let mut registry = Registry { next_topic_id: 1, ..Default::default() };
+    for predefined in args.iter().skip(3) {
+        let (topic_id, topic) = predefined.split_once('=')
+            .ok_or(anyhow!("Predefined topic '{predefined}' is not of the form id=topic"))?;
+        let topic_id: u16 = topic_id.parse()?;
+        // 0x0000 and 0xFFFF are reserved, which also keeps the next registered ID in range
+        if topic_id == 0 || topic_id == 0xffff {
+            return Err(anyhow!("Predefined topic ID {topic_id} is reserved"));
+        }
+        registry.topic_ids.insert(topic.to_string(), topic_id);
+        registry.topics.insert(topic_id, topic.to_string());
+        registry.next_topic_id = registry.next_topic_id.max(topic_id + 1);
+    }
+
+    let host = format!("mqtt://{broker_addr}");
+    println!("Connecting to MQTT broker at {}", host);
+
+    let opts = mqtt::CreateOptionsBuilder::new()
+        .server_uri(host)
+        .client_id("mqttsn_gateway")
+        .finalize();
+    let client = mqtt::Client::new(opts)?;
+    let rx = client.start_consuming();
+
+    let conn_opts = mqtt::ConnectOptionsBuilder::new()
+        .keep_alive_interval(Duration::from_secs(20))
+        .clean_session(true)
+        .finalize();
+    client.connect(conn_opts)?;
+    println!("Connected to broker");
+
+    let socket = UdpSocket::bind(&addr)?;
+    // Time out regularly to notice the exit request
+    socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+    let gateway = Arc::new(Gateway {
+        socket: socket.try_clone()?,
+        client: client.clone(),
+        registry: Mutex::new(registry),
+    });
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    let exit_client = client.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+        exit_client.stop_consuming();
+    }).expect("Error setting up exit handler");
+
+    let broker_gateway = gateway.clone();
+    let broker_thread = std::thread::spawn(move || {
+        let mut msg_id: u16 = 0;
+        for msg in rx.iter() {
+            let Some(msg) = msg else {
+                continue;
+            };
+            msg_id = msg_id.wrapping_add(1).max(1);
+            broker_gateway.forward(&msg, msg_id);
+        }
+    });
+
+    println!("Waiting for messages..");
+    let mut buffer = vec![0u8; 65536];
+    while running.load(Ordering::SeqCst) {
+        let (size, addr) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+            Err(e) => {
+                println!("Receive error: {e}");
+                continue;
+            }
+        };
+
+        let result = Packet::decode(&buffer[..size])
+            .and_then(|packet| gateway.handle(addr, packet));
+        if let Err(e) = result {
+            println!("Error handling packet from {addr}: {e}");
+        }
+    }
+
+    println!("Shutting down");
+    let _ = broker_thread.join();
+    if client.is_connected() {
+        client.disconnect(None)?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_ids_exhausted() {
+        let mut registry = Registry { next_topic_id: 0xfffd, ..Default::default() };
+        assert_eq!(registry.topic_id("a"), Some(0xfffd));
+        assert_eq!(registry.topic_id("b"), Some(0xfffe));
+        assert_eq!(registry.topic_id("c"), None);
+        assert_eq!(registry.topic_id("c"), None);
+        // Registered topics keep their IDs
+        assert_eq!(registry.topic_id("a"), Some(0xfffd));
+        assert!(!registry.topics.contains_key(&0xffff));
+    }
+}
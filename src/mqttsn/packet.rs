@@ -0,0 +1,241 @@
+use anyhow::{anyhow, bail, Result};
+
+pub const FLAG_CLEAN_SESSION: u8 = 0x04;
+
+pub const TOPIC_NORMAL: u8 = 0x00;
+pub const TOPIC_PREDEFINED: u8 = 0x01;
+const TOPIC_TYPE_MASK: u8 = 0x03;
+
+pub const RC_ACCEPTED: u8 = 0x00;
+pub const RC_CONGESTION: u8 = 0x01;
+pub const RC_INVALID_TOPIC_ID: u8 = 0x02;
+pub const RC_NOT_SUPPORTED: u8 = 0x03;
+
+const PROTOCOL_ID: u8 = 0x01;
+
+const CONNECT: u8 = 0x04;
+const CONNACK: u8 = 0x05;
+const REGISTER: u8 = 0x0a;
+const REGACK: u8 = 0x0b;
+const PUBLISH: u8 = 0x0c;
+const PUBACK: u8 = 0x0d;
+const SUBSCRIBE: u8 = 0x12;
+const SUBACK: u8 = 0x13;
+const PINGREQ: u8 = 0x16;
+const PINGRESP: u8 = 0x17;
+const DISCONNECT: u8 = 0x18;
+
+/// Flags carrying the QoS level, -1 publishes without a connection.
+pub fn qos_flags(qos: i8) -> u8 {
+    match qos {
+        -1 => 0x60,
+        1 => 0x20,
+        2 => 0x40,
+        _ => 0x00,
+    }
+}
+
+pub fn flags_qos(flags: u8) -> i8 {
+    match (flags >> 5) & 0x03 {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        _ => -1,
+    }
+}
+
+pub fn topic_type(flags: u8) -> u8 {
+    flags & TOPIC_TYPE_MASK
+}
+
+/// The MQTT-SN 1.2 packets used by the client and the gateway.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Packet {
+    Connect { flags: u8, duration: u16, client_id: String },
+    Connack { return_code: u8 },
+    Register { topic_id: u16, msg_id: u16, topic_name: String },
+    Regack { topic_id: u16, msg_id: u16, return_code: u8 },
+    Publish { flags: u8, topic_id: u16, msg_id: u16, data: Vec<u8> },
+    Puback { topic_id: u16, msg_id: u16, return_code: u8 },
+    /// Only subscriptions by topic name are supported.
+    Subscribe { flags: u8, msg_id: u16, topic_name: String },
+    Suback { flags: u8, topic_id: u16, msg_id: u16, return_code: u8 },
+    Pingreq,
+    Pingresp,
+    Disconnect,
+}
+
+impl Packet {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        let msg_type = match self {
+            Packet::Connect { flags, duration, client_id } => {
+                body.extend_from_slice(&[*flags, PROTOCOL_ID]);
+                body.extend_from_slice(&duration.to_be_bytes());
+                body.extend_from_slice(client_id.as_bytes());
+                CONNECT
+            }
+            Packet::Connack { return_code } => {
+                body.push(*return_code);
+                CONNACK
+            }
+            Packet::Register { topic_id, msg_id, topic_name } => {
+                body.extend_from_slice(&topic_id.to_be_bytes());
+                body.extend_from_slice(&msg_id.to_be_bytes());
+                body.extend_from_slice(topic_name.as_bytes());
+                REGISTER
+            }
+            Packet::Regack { topic_id, msg_id, return_code } => {
+                body.extend_from_slice(&topic_id.to_be_bytes());
+                body.extend_from_slice(&msg_id.to_be_bytes());
+                body.push(*return_code);
+                REGACK
+            }
+            Packet::Publish { flags, topic_id, msg_id, data } => {
+                body.push(*flags);
+                body.extend_from_slice(&topic_id.to_be_bytes());
+                body.extend_from_slice(&msg_id.to_be_bytes());
+                body.extend_from_slice(data);
+                PUBLISH
+            }
+            Packet::Puback { topic_id, msg_id, return_code } => {
+                body.extend_from_slice(&topic_id.to_be_bytes());
+                body.extend_from_slice(&msg_id.to_be_bytes());
+                body.push(*return_code);
+                PUBACK
+            }
+            Packet::Subscribe { flags, msg_id, topic_name } => {
+                body.push((flags & !TOPIC_TYPE_MASK) | TOPIC_NORMAL);
+                body.extend_from_slice(&msg_id.to_be_bytes());
+                body.extend_from_slice(topic_name.as_bytes());
+                SUBSCRIBE
+            }
+            Packet::Suback { flags, topic_id, msg_id, return_code } => {
+                body.push(*flags);
+                body.extend_from_slice(&topic_id.to_be_bytes());
+                body.extend_from_slice(&msg_id.to_be_bytes());
+                body.push(*return_code);
+                SUBACK
+            }
+            Packet::Pingreq => PINGREQ,
+            Packet::Pingresp => PINGRESP,
+            Packet::Disconnect => DISCONNECT,
+        };
+
+        // The length counts itself, three bytes are needed beyond 255
+        let mut packet = Vec::with_capacity(body.len() + 4);
+        if body.len() + 2 <= 0xff {
+            packet.push((body.len() + 2) as u8);
+        } else {
+            packet.push(0x01);
+            packet.extend_from_slice(&((body.len() + 4) as u16).to_be_bytes());
+        }
+        packet.push(msg_type);
+        packet.extend_from_slice(&body);
+        return packet;
+    }
+
+    pub fn decode(packet: &[u8]) -> Result<Self> {
+        let (length, header_len) = match packet.first() {
+            Some(0x01) if packet.len() >= 3 => (u16::from_be_bytes([packet[1], packet[2]]) as usize, 3),
+            Some(&length) => (length as usize, 1),
+            None => bail!("Empty packet"),
+        };
+        if length != packet.len() || length <= header_len {
+            bail!("Length {length} does not match packet of {} bytes", packet.len());
+        }
+
+        let msg_type = packet[header_len];
+        let body = &packet[header_len + 1..];
+        let u16_at = |pos: usize| -> Result<u16> {
+            let bytes = body.get(pos..pos + 2).ok_or(anyhow!("Packet too short"))?;
+            return Ok(u16::from_be_bytes([bytes[0], bytes[1]]));
+        };
+        let u8_at = |pos: usize| -> Result<u8> {
+            return body.get(pos).copied().ok_or(anyhow!("Packet too short"));
+        };
+        let text_from = |pos: usize| -> Result<String> {
+            return Ok(String::from_utf8(body.get(pos..).unwrap_or_default().to_vec())?);
+        };
+
+        let packet = match msg_type {
+            CONNECT => Packet::Connect { flags: u8_at(0)?, duration: u16_at(2)?, client_id: text_from(4)? },
+            CONNACK => Packet::Connack { return_code: u8_at(0)? },
+            REGISTER => Packet::Register { topic_id: u16_at(0)?, msg_id: u16_at(2)?, topic_name: text_from(4)? },
+            REGACK => Packet::Regack { topic_id: u16_at(0)?, msg_id: u16_at(2)?, return_code: u8_at(4)? },
+            PUBLISH => Packet::Publish {
+                flags: u8_at(0)?,
+                topic_id: u16_at(1)?,
+                msg_id: u16_at(3)?,
+                data: body.get(5..).unwrap_or_default().to_vec(),
+            },
+            PUBACK => Packet::Puback { topic_id: u16_at(0)?, msg_id: u16_at(2)?, return_code: u8_at(4)? },
+            SUBSCRIBE => Packet::Subscribe { flags: u8_at(0)?, msg_id: u16_at(1)?, topic_name: text_from(3)? },
+            SUBACK => Packet::Suback { flags: u8_at(0)?, topic_id: u16_at(1)?, msg_id: u16_at(3)?, return_code: u8_at(5)? },
+            PINGREQ => Packet::Pingreq,
+            PINGRESP => Packet::Pingresp,
+            DISCONNECT => Packet::Disconnect,
+            _ => bail!("Unsupported message type {msg_type:#04x}"),
+        };
+        return Ok(packet);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let packets = [
+            Packet::Connect { flags: FLAG_CLEAN_SESSION, duration: 60, client_id: "bench".to_string() },
+            Packet::Connack { return_code: RC_ACCEPTED },
+            Packet::Register { topic_id: 0, msg_id: 1, topic_name: "mqtt_send".to_string() },
+            Packet::Regack { topic_id: 2, msg_id: 1, return_code: RC_ACCEPTED },
+            Packet::Publish { flags: qos_flags(1) | TOPIC_PREDEFINED, topic_id: 2, msg_id: 3, data: vec![1, 2, 3] },
+            Packet::Puback { topic_id: 2, msg_id: 3, return_code: RC_INVALID_TOPIC_ID },
+            Packet::Subscribe { flags: qos_flags(0), msg_id: 4, topic_name: "mqtt_recv".to_string() },
+            Packet::Suback { flags: qos_flags(0), topic_id: 3, msg_id: 4, return_code: RC_ACCEPTED },
+            Packet::Pingreq,
+            Packet::Pingresp,
+            Packet::Disconnect,
+        ];
+
+        for packet in packets {
+            assert_eq!(Packet::decode(&packet.encode()).unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn long_length() {
+        // 253 body bytes still fit the one byte length, one more needs three bytes
+        for (data_len, header_len) in [(248, 1), (249, 3), (1000, 3)] {
+            let packet = Packet::Publish { flags: 0, topic_id: 1, msg_id: 0, data: vec![7; data_len] };
+            let buf = packet.encode();
+            assert_eq!(buf.len(), header_len + 1 + 5 + data_len);
+            assert_eq!(buf[0] == 0x01, header_len == 3);
+            assert_eq!(Packet::decode(&buf).unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn flags() {
+        for qos in [-1, 0, 1, 2] {
+            assert_eq!(flags_qos(qos_flags(qos) | TOPIC_PREDEFINED), qos);
+        }
+        assert_eq!(topic_type(qos_flags(-1) | TOPIC_PREDEFINED), TOPIC_PREDEFINED);
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert!(Packet::decode(&[]).is_err());
+        // Length does not match the packet
+        assert!(Packet::decode(&[0x05, CONNACK, 0]).is_err());
+        assert!(Packet::decode(&[0x01, 0x00, 0x05, PINGREQ]).is_err());
+        // Fields missing
+        assert!(Packet::decode(&[0x04, REGACK, 0, 1]).is_err());
+        assert!(Packet::decode(&[0x02, 0x7f]).is_err());
+        // Topic names must be UTF-8
+        assert!(Packet::decode(&[0x07, REGISTER, 0, 0, 0, 1, 0xff]).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use anyhow::anyhow;
+use async_nats::{jetstream, Client, Subscriber};
+use futures::StreamExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::{Handle, Runtime};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure, index_from_message};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, NatsConfig, NatsMode};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+/// Where the messages go and how the echo finds its way back.
+enum Target {
+    /// The echo publishes on the configured receive subject.
+    Subject,
+    /// Each message carries its own reply subject below this inbox.
+    Inbox(String),
+    /// The stream acknowledges every message, failed acknowledgements are counted.
+    Stream(jetstream::Context, Arc<AtomicUsize>),
+}
+
+struct NatsSender {
+    client: Client,
+    handle: Handle,
+    subject: String,
+    target: Target,
+}
+
+impl NatsSender {
+    fn publish(&self, msg: MsgType) -> anyhow::Result<()> {
+        let subject = self.subject.clone();
+        return self.handle.block_on(async {
+            match &self.target {
+                Target::Subject => self.client.publish(subject, msg.into()).await?,
+                Target::Inbox(inbox) => {
+                    let reply = format!("{inbox}.{}", index_from_message(msg.clone())?);
+                    self.client.publish_with_reply(subject, reply, msg.into()).await?;
+                }
+                Target::Stream(context, num_ack_errors) => {
+                    // Waiting for the stream to store the message would hold up the next one
+                    let ack = context.publish(subject, msg.into()).await?;
+                    let num_ack_errors = num_ack_errors.clone();
+                    self.handle.spawn(async move {
+                        if let Err(e) = ack.await {
+                            println!("JetStream ack error: {e}");
+                            num_ack_errors.fetch_add(1, Ordering::Relaxed);
+                        }
+                    });
+                }
+            }
+            return Ok(());
+        });
+    }
+}
+
+impl Sender for NatsSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        return self.publish(msg).map_err(|e| {
+            match self.client.connection_state() {
+                async_nats::connection::State::Connected => BenchError::send(e),
+                _ => BenchError::connect(e),
+            }
+        });
+    }
+}
+
+impl Drop for NatsSender {
+    fn drop(&mut self) {
+        if let Target::Stream(_, num_ack_errors) = &self.target {
+            println!("{} JetStream publishes not acknowledged", num_ack_errors.load(Ordering::Relaxed));
+        }
+    }
+}
+
+struct NatsReceiver {
+    subscriber: Subscriber,
+    handle: Handle,
+}
+
+impl Receiver for NatsReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let next = self.handle.block_on(async {
+            return tokio::time::timeout(timeout, self.subscriber.next()).await;
+        });
+
+        match next {
+            Ok(Some(msg)) => Ok(Some(msg.payload.to_vec())),
+            Ok(None) => Err(BenchError::connect(anyhow!("Subscription closed"))),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+fn run_bench(config: &NatsConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let runtime = Runtime::new().map_err(BenchError::connect)?;
+    let handle = runtime.handle().clone();
+
+    let client = runtime.block_on(async_nats::connect(&config.address)).map_err(BenchError::connect)?;
+
+    let (target, subject_recv) = match config.mode {
+        NatsMode::PubSub => {
+            (Target::Subject, config.subject_recv.clone())
+        }
+        NatsMode::Request => {
+            let inbox = client.new_inbox();
+            (Target::Inbox(inbox.clone()), format!("{inbox}.*"))
+        }
+        NatsMode::JetStream => {
+            let context = jetstream::new(client.clone());
+            runtime.block_on(context.get_or_create_stream(jetstream::stream::Config {
+                name: config.stream.clone(),
+                subjects: vec![config.subject_send.clone()],
+                ..Default::default()
+            })).map_err(BenchError::protocol)?;
+            (Target::Stream(context, Arc::default()), config.subject_recv.clone())
+        }
+    };
+
+    let subscriber = runtime.block_on(async {
+        let subscriber = client.subscribe(subject_recv).await?;
+        // Make sure the server knows about the subscription before the first echo
+        client.flush().await?;
+        return anyhow::Ok(subscriber);
+    }).map_err(BenchError::protocol)?;
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let send = NatsSender {
+        client,
+        handle: handle.clone(),
+        subject: config.subject_send.clone(),
+        target,
+    };
+    let recv = NatsReceiver { subscriber, handle };
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    return bench.run(send, recv);
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.nats.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_broker(Readiness::Listening(config.nats.address.clone())).unwrap();
+        supervisor.spawn_echo(
+            "nats-echo",
+            &[
+                &config.nats.address,
+                config.nats.mode.name(),
+                &config.nats.subject_send,
+                &config.nats.subject_recv,
+                &config.nats.stream,
+            ],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.nats.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.nats,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.nats.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
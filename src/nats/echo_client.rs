@@ -0,0 +1,110 @@
+use anyhow::Result;
+use async_nats::jetstream;
+use async_nats::jetstream::consumer::{pull, DeliverPolicy};
+use futures::StreamExt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::time::timeout;
+
+// Time out regularly to notice the exit request
+const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Echoes core messages to their reply subject, or to `subject_recv` if they have none.
+async fn echo_core(client: async_nats::Client, subject_send: String, subject_recv: String, running: &AtomicBool) -> Result<()> {
+    let mut subscriber = client.subscribe(subject_send).await?;
+    client.flush().await?;
+
+    println!("Waiting for messages..");
+    while running.load(Ordering::SeqCst) {
+        let msg = match timeout(RECV_TIMEOUT, subscriber.next()).await {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            Err(_) => continue,
+        };
+
+        let subject = msg.reply.map(|reply| reply.to_string()).unwrap_or(subject_recv.clone());
+        if let Err(e) = client.publish(subject, msg.payload).await {
+            println!("Publish error: {e}");
+        }
+    }
+
+    return Ok(());
+}
+
+/// Consumes the stream and echoes every stored message on `subject_recv`.
+///
+/// A core subscription would also see the ack requests of the publishers,
+/// so the messages are taken from a consumer instead.
+async fn echo_stream(client: async_nats::Client, subject_send: String, subject_recv: String, stream: String, running: &AtomicBool) -> Result<()> {
+    let context = jetstream::new(client.clone());
+    let stream = context.get_or_create_stream(jetstream::stream::Config {
+        name: stream,
+        subjects: vec![subject_send],
+        ..Default::default()
+    }).await?;
+
+    // Messages stored before the echo came up are not echoed
+    let consumer = stream.create_consumer(pull::Config {
+        deliver_policy: DeliverPolicy::New,
+        ..Default::default()
+    }).await?;
+    let mut messages = consumer.messages().await?;
+
+    println!("Waiting for messages..");
+    while running.load(Ordering::SeqCst) {
+        let msg = match timeout(RECV_TIMEOUT, messages.next()).await {
+            Ok(Some(Ok(msg))) => msg,
+            Ok(Some(Err(e))) => {
+                println!("Consumer error: {e}");
+                continue;
+            }
+            Ok(None) => break,
+            Err(_) => continue,
+        };
+
+        if let Err(e) = client.publish(subject_recv.clone(), msg.payload.clone()).await {
+            println!("Publish error: {e}");
+        }
+        if let Err(e) = msg.ack().await {
+            println!("Ack error: {e}");
+        }
+    }
+
+    return Ok(());
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |idx: usize, default: &str| args.get(idx).cloned().unwrap_or(default.to_string());
+
+    let addr = arg(1, "localhost:4222");
+    let mode = arg(2, "pub_sub");
+    let subject_send = arg(3, "nats_send");
+    let subject_recv = arg(4, "nats_recv");
+    let stream = arg(5, "BENCH");
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        println!("Connecting to NATS server at {addr}");
+        let client = async_nats::connect(&addr).await?;
+
+        match mode.as_str() {
+            "jet_stream" => echo_stream(client.clone(), subject_send, subject_recv, stream, &running).await?,
+            _ => echo_core(client.clone(), subject_send, subject_recv, &running).await?,
+        }
+
+        client.flush().await?;
+        return anyhow::Ok(());
+    })?;
+
+    println!("Shutting down");
+    return Ok(());
+}
@@ -1,5 +1,5 @@
-use std::{sync::Arc, time::{Duration, Instant}};
-use anyhow::Result;
+use std::{sync::Arc, time::Duration};
+use anyhow::{anyhow, Result};
 
 use opcua::{client::prelude::*, sync::RwLock};
 use opcua::{
@@ -12,11 +12,16 @@ use opcua::{
 
 #[path = "../benchmarker.rs"]
 mod benchmarker;
-use benchmarker::{index_from_message, Benchmarker, MsgType, Receiver, Sender};
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
 
 #[path = "../config.rs"]
 mod config;
-use config::Config;
+use config::{Config, OpcuaConfig};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
 
 const DEFAULT_URL: &str = "opc.tcp://localhost:4855";
 
@@ -30,62 +35,29 @@ impl OpcuaSender {
 }
 
 impl Sender for OpcuaSender {
-    fn send(&mut self, msg: MsgType) -> Result<()> {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
         Ok(())
     }
 }
 
 struct OpcuaReceiver {
-    num_messages: usize,
-    duration: Duration,
-    message_size: usize,
 }
 
 impl OpcuaReceiver {
-    pub fn new(
-        num_messages: usize,
-        duration: Duration,
-        message_size: usize,
-    ) -> Self {
-        Self {
-            num_messages,
-            duration,
-            message_size,
-        }
+    pub fn new() -> Self {
+        Self {  }
     }
 }
 
 impl Receiver for OpcuaReceiver {
-    fn listen(&mut self) -> Result<Vec<Option<Instant>>> {
-        let mut msgs = vec![None; self.num_messages];
-
-        let mut num_received = 0;
-        let time_start = Instant::now();
-        loop {
-            if num_received == self.num_messages {
-                break;
-            }
-
-            if time_start.elapsed() > self.duration + Duration::from_secs(5) {
-                break;
-            }
-
-            let mut msg = vec![0u8; self.message_size*8 + 8];
-
-            num_received += 1;
-
-            let Ok(idx) = index_from_message(msg) else {
-                println!("parse error");
-                continue;
-            };
-            msgs[idx] = Some(Instant::now());
-        }
-
-        return Ok(msgs);
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        std::thread::sleep(timeout);
+        Ok(None)
     }
 }
 
-async fn run_bench(addr: &String, num_messages: usize, duration: Duration, message_size: usize) {
+fn run_bench(config: &OpcuaConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let addr = &config.address;
 
     // Make the client configuration
     let mut client = ClientBuilder::new()
@@ -96,51 +68,86 @@ async fn run_bench(addr: &String, num_messages: usize, duration: Duration, messa
         .create_sample_keypair(true)
         .session_retry_limit(3)
         .client()
-        .unwrap();
+        .ok_or(BenchError::connect(anyhow!("Invalid client configuration")))?;
 
-    let endpoint: EndpointDescription = ("opc.tcp://localhost:4855/", "None", MessageSecurityMode::None, UserTokenPolicy::anonymous()).into();
+    let url = format!("opc.tcp://{addr}/");
+    let endpoint: EndpointDescription = (url.as_str(), "None", MessageSecurityMode::None, UserTokenPolicy::anonymous()).into();
 
     // Create the session
-    let session = client.connect_to_endpoint(endpoint, IdentityToken::Anonymous).unwrap();
+    let session = client.connect_to_endpoint(endpoint, IdentityToken::Anonymous)
+        .map_err(BenchError::connect)?;
 
-    if subscribe_to_values(session.clone()).is_ok() {
-        let _ = Session::run(session);
+    // Keeps the session alive in the background for the duration of the step
+    let session_tx = if subscribe_to_values(session.clone()).is_ok() {
+        Some(Session::run_async(session))
     } else {
         println!("Error creating subscription");
-    }
+        None
+    };
 
     // Increase size to add  message number
-    let message_size = message_size + 8;
+    let message_size = config.message_size + 8;
     println!("connected");
 
     let send = OpcuaSender::new();
-    let recv = OpcuaReceiver::new(num_messages, duration, message_size);
+    let recv = OpcuaReceiver::new();
     let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
 
     let stats = bench.run(send, recv);
-    dbg!(&stats);
-    //stream.shutdown(std::net::Shutdown::Both).unwrap();
+
+    if let Some(session_tx) = session_tx {
+        let _ = session_tx.send(SessionCommand::Stop);
+    }
+
+    return stats;
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+// The opcua client runs its own runtime, so main must not be async
+fn main() -> Result<()> {
+    handle_interrupt();
+
     let config: Config = toml::from_str(
         &std::fs::read_to_string("config.toml").unwrap()
     ).unwrap();
 
-    let schedule = config.opcua.schedule;
+    let _supervisor = config.opcua.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "opcua-echo",
+            &[&config.opcua.address],
+            Readiness::Listening(config.opcua.address.clone()),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.opcua.schedule.clone();
 
     let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
     for i in 0..schedule.steps {
         let duration = Duration::from_secs(schedule.secs_per_step);
         let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
 
-        run_bench(
-            &config.opcua.address,
+        let result = run_bench(
+            &config.opcua,
             num_messages.floor() as usize, 
             duration, 
-            config.opcua.message_size,
         );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.opcua.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
     }
 
     Ok(())
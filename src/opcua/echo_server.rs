@@ -1,4 +1,7 @@
+use std::sync::Arc;
+
 use opcua::server::{prelude::*, callbacks};
+use opcua::sync::RwLock;
 
 struct Echo {}
 
@@ -24,6 +27,17 @@ impl callbacks::Method for Echo {
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let (host, port) = match args.get(1) {
+        Some(addr) => {
+            let (host, port) = addr.rsplit_once(':').expect("Address must be host:port");
+            (host.to_string(), port.parse::<u16>().expect("Invalid port"))
+        }
+        None => (hostname().unwrap(), 4343),
+    };
+
     let server: Server = ServerBuilder::new()
         .application_name("opcua_bench")
         .application_uri("urn:opcua_bench")
@@ -36,7 +50,7 @@ fn main() {
         .multi_threaded_executor()
         .create_sample_keypair(false)
         .discovery_server_url(None)
-        .host_and_port(hostname().unwrap(), 4343)
+        .host_and_port(host, port)
         .server()
         .unwrap();
 
@@ -45,26 +59,36 @@ fn main() {
         let mut address_space = address_space.write();
         address_space.register_namespace("urn:opcua_bench").unwrap()
     };
-    let address_space = server.address_space();
-    let mut address_space = address_space.write();
 
-    let node_id = NodeId::new(ns, "echo");
+    {
+        let address_space = server.address_space();
+        let mut address_space = address_space.write();
+
+        let node_id = NodeId::new(ns, "echo");
 
-    MethodBuilder::new(&NodeId::new(ns, "opcua_req"), "opcua_req", "opcua_req")
-        .component_of(node_id.clone())
-        .input_args(
-            &mut address_space,
-            &[
-                ("request", DataTypeId::ByteString).into(),
-            ],
-        )
-        .output_args(
-            &mut address_space,
-            &[("repsonse", DataTypeId::ByteString).into()],
-        )
-        .callback(Box::new(Echo{}))
-        .insert(&mut address_space);
+        MethodBuilder::new(&NodeId::new(ns, "opcua_req"), "opcua_req", "opcua_req")
+            .component_of(node_id.clone())
+            .input_args(
+                &mut address_space,
+                &[
+                    ("request", DataTypeId::ByteString).into(),
+                ],
+            )
+            .output_args(
+                &mut address_space,
+                &[("repsonse", DataTypeId::ByteString).into()],
+            )
+            .callback(Box::new(Echo{}))
+            .insert(&mut address_space);
+    }
 
-    server.run();
-}
+    // The server finishes its sessions and returns from `run_server` once aborted
+    let server = Arc::new(RwLock::new(server));
+    let exit_server = server.clone();
+    ctrlc::set_handler(move || {
+        exit_server.write().abort();
+    }).expect("Error setting up exit handler");
 
+    Server::run_server(server);
+    println!("Shutting down");
+}
@@ -0,0 +1,234 @@
+use anyhow::anyhow;
+use quinn::rustls::pki_types::CertificateDer;
+use quinn::rustls::RootCertStore;
+use quinn::{ClientConfig, Connection, Endpoint, RecvStream, SendStream, VarInt};
+use std::net::SocketAddr;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use tokio::runtime::{Handle, Runtime};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, QuicConfig, QuicMode};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+/// Trusts only the certificate the echo generated.
+fn client_config(cert_path: &str) -> anyhow::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from(std::fs::read(cert_path)?))?;
+    return Ok(ClientConfig::with_root_certificates(Arc::new(roots))?);
+}
+
+struct StreamSender {
+    send: SendStream,
+    handle: Handle,
+}
+
+impl Sender for StreamSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        return self.handle.block_on(self.send.write_all(&msg)).map_err(BenchError::send);
+    }
+}
+
+struct StreamReceiver {
+    recv: RecvStream,
+    handle: Handle,
+    buffer: Vec<u8>,
+    message_size: usize,
+}
+
+impl Receiver for StreamReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        // The stream has no framing, so reads are collected until a full message is buffered
+        let time_start = Instant::now();
+        while self.buffer.len() < self.message_size {
+            let remaining = timeout.saturating_sub(time_start.elapsed());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+
+            let mut chunk = vec![0u8; self.message_size];
+            let read = self.handle.block_on(async {
+                return tokio::time::timeout(remaining, self.recv.read(&mut chunk)).await;
+            });
+
+            match read {
+                Ok(Ok(Some(size))) => self.buffer.extend_from_slice(&chunk[..size]),
+                Ok(Ok(None)) => return Err(BenchError::connect(anyhow!("Stream finished by server"))),
+                Ok(Err(e)) => return Err(BenchError::connect(e)),
+                Err(_) => return Ok(None),
+            }
+        }
+
+        let rest = self.buffer.split_off(self.message_size);
+        return Ok(Some(std::mem::replace(&mut self.buffer, rest)));
+    }
+}
+
+/// Opens a stream per message without waiting for the previous ones.
+struct StreamPerMessageSender {
+    connection: Connection,
+    handle: Handle,
+    tx: mpsc::Sender<BenchResult<MsgType>>,
+}
+
+impl Sender for StreamPerMessageSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let connection = self.connection.clone();
+        let tx = self.tx.clone();
+
+        self.handle.spawn(async move {
+            let result = async {
+                let (mut send, mut recv) = connection.open_bi().await?;
+                send.write_all(&msg).await?;
+                send.finish()?;
+                return anyhow::Ok(recv.read_to_end(msg.len()).await?);
+            }.await;
+            let _ = tx.send(result.map_err(BenchError::receive));
+        });
+        return Ok(());
+    }
+}
+
+/// Hands the replies collected by the stream tasks to the benchmarker.
+struct ChannelReceiver {
+    rx: mpsc::Receiver<BenchResult<MsgType>>,
+}
+
+impl Receiver for ChannelReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(result) => result.map(Some),
+            // The sender holds a handle as well, so this only times out
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+struct DatagramSender {
+    connection: Connection,
+}
+
+impl Sender for DatagramSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        return self.connection.send_datagram(msg.into()).map_err(BenchError::send);
+    }
+}
+
+struct DatagramReceiver {
+    connection: Connection,
+    handle: Handle,
+}
+
+impl Receiver for DatagramReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let datagram = self.handle.block_on(async {
+            return tokio::time::timeout(timeout, self.connection.read_datagram()).await;
+        });
+
+        match datagram {
+            Ok(Ok(datagram)) => Ok(Some(datagram.to_vec())),
+            Ok(Err(e)) => Err(BenchError::connect(e)),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+fn run_bench(config: &QuicConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let runtime = Runtime::new().map_err(BenchError::connect)?;
+    let handle = runtime.handle().clone();
+
+    let addr: SocketAddr = config.address.parse().map_err(BenchError::connect)?;
+    let client_config = client_config(&config.cert_path).map_err(BenchError::connect)?;
+
+    let (endpoint, connection) = runtime.block_on(async {
+        let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+        endpoint.set_default_client_config(client_config);
+        let connection = endpoint.connect(addr, &config.server_name)?.await?;
+        return anyhow::Ok((endpoint, connection));
+    }).map_err(BenchError::connect)?;
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    let result = match config.mode {
+        QuicMode::Stream => {
+            let (send, recv) = runtime.block_on(connection.open_bi()).map_err(BenchError::connect)?;
+            let send = StreamSender { send, handle: handle.clone() };
+            let recv = StreamReceiver { recv, handle, buffer: Vec::new(), message_size };
+            bench.run(send, recv)
+        }
+        QuicMode::StreamPerMessage => {
+            let (tx, rx) = mpsc::channel();
+            let send = StreamPerMessageSender { connection: connection.clone(), handle, tx };
+            bench.run(send, ChannelReceiver { rx })
+        }
+        QuicMode::Datagram => {
+            let send = DatagramSender { connection: connection.clone() };
+            let recv = DatagramReceiver { connection: connection.clone(), handle };
+            bench.run(send, recv)
+        }
+    };
+
+    connection.close(VarInt::from_u32(0), b"done");
+    runtime.block_on(endpoint.wait_idle());
+    return result;
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.quic.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "quic-echo",
+            &[&config.quic.address, &config.quic.server_name, &config.quic.cert_path],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.quic.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.quic,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.quic.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
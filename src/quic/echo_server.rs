@@ -0,0 +1,106 @@
+use anyhow::Result;
+use quinn::rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+use quinn::{Connection, Endpoint, Incoming, RecvStream, SendStream, ServerConfig, VarInt};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::time::timeout;
+
+// Time out regularly to notice the exit request
+const ACCEPT_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Enough for one stream per message at the highest rates of the schedule
+const MAX_STREAMS: u32 = 4096;
+
+/// Issues a self-signed certificate for `server_name` and stores it for the bench.
+fn server_config(server_name: &str, cert_path: &str) -> Result<ServerConfig> {
+    let certified = rcgen::generate_simple_self_signed(vec![server_name.to_string()])?;
+    let cert = certified.cert.der().clone();
+    std::fs::write(cert_path, &cert)?;
+
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+    let mut config = ServerConfig::with_single_cert(vec![cert], key)?;
+    Arc::get_mut(&mut config.transport).unwrap()
+        .max_concurrent_bidi_streams(VarInt::from_u32(MAX_STREAMS));
+    return Ok(config);
+}
+
+/// Writes back whatever arrives until the peer finishes its side of the stream.
+async fn echo_stream(mut send: SendStream, mut recv: RecvStream) -> Result<()> {
+    let mut buffer = vec![0u8; 64 * 1024];
+    while let Some(size) = recv.read(&mut buffer).await? {
+        send.write_all(&buffer[..size]).await?;
+    }
+    send.finish()?;
+    return Ok(());
+}
+
+async fn echo_datagrams(connection: Connection) {
+    while let Ok(datagram) = connection.read_datagram().await {
+        if let Err(e) = connection.send_datagram(datagram) {
+            println!("Datagram error: {e}");
+        }
+    }
+}
+
+async fn handle_connection(incoming: Incoming) -> Result<()> {
+    let connection = incoming.await?;
+    tokio::spawn(echo_datagrams(connection.clone()));
+
+    // Ends once the client closes the connection
+    while let Ok((send, recv)) = connection.accept_bi().await {
+        tokio::spawn(async move {
+            if let Err(e) = echo_stream(send, recv).await {
+                println!("Stream error: {e}");
+            }
+        });
+    }
+    return Ok(());
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args()
+        .collect();
+    let arg = |idx: usize, default: &str| args.get(idx).cloned().unwrap_or(default.to_string());
+
+    let addr: SocketAddr = arg(1, "127.0.0.1:4433").parse()?;
+    let server_name = arg(2, "localhost");
+    let cert_path = arg(3, "/tmp/iot-bench-quic.der");
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let config = server_config(&server_name, &cert_path)?;
+
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        let endpoint = Endpoint::server(config, addr)?;
+
+        println!("Waiting for messages..");
+        while running.load(Ordering::SeqCst) {
+            let incoming = match timeout(ACCEPT_TIMEOUT, endpoint.accept()).await {
+                Ok(Some(incoming)) => incoming,
+                Ok(None) => break,
+                Err(_) => continue,
+            };
+
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(incoming).await {
+                    println!("Connection error: {e}");
+                }
+            });
+        }
+
+        endpoint.close(VarInt::from_u32(0), b"shutdown");
+        return anyhow::Ok(());
+    })?;
+
+    let _ = std::fs::remove_file(&cert_path);
+    println!("Shutting down");
+    return Ok(());
+}
@@ -0,0 +1,234 @@
+use anyhow::anyhow;
+use redis::{Commands, Connection, RedisError};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, RedisConfig, RedisMode};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod streams;
+
+// Lets the subscriber thread notice that the receiver is gone
+const SUBSCRIBER_TIMEOUT: Duration = Duration::from_millis(100);
+const READ_COUNT: usize = 100;
+
+struct PubSubSender {
+    con: Connection,
+    channel: String,
+}
+
+impl Sender for PubSubSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let published: redis::RedisResult<usize> = self.con.publish(&self.channel, msg);
+        published.map_err(|e| redis_error(e, BenchError::send))?;
+        return Ok(());
+    }
+}
+
+/// Subscribes in a thread of its own, as the subscription borrows the connection.
+///
+/// Returns once the server confirmed the subscription.
+fn subscribe(mut con: Connection, channel: String) -> BenchResult<ChannelReceiver> {
+    let (sender, receiver) = mpsc::channel();
+    let (ready_sender, ready_receiver) = mpsc::channel();
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
+
+    std::thread::spawn(move || {
+        let mut pubsub = con.as_pubsub();
+        let subscribed = pubsub.subscribe(&channel)
+            .and_then(|_| pubsub.set_read_timeout(Some(SUBSCRIBER_TIMEOUT)));
+        let failed = subscribed.is_err();
+        let _ = ready_sender.send(subscribed);
+        if failed {
+            return;
+        }
+
+        while thread_running.load(Ordering::SeqCst) {
+            let result = match pubsub.get_message() {
+                Ok(msg) => Ok(msg.get_payload_bytes().to_vec()),
+                Err(e) if e.is_timeout() => continue,
+                Err(e) => Err(redis_error(e, BenchError::receive)),
+            };
+
+            let is_connect_error = matches!(result, Err(BenchError::Connect(_)));
+            if sender.send(result).is_err() || is_connect_error {
+                break;
+            }
+        }
+    });
+
+    ready_receiver.recv()
+        .map_err(|_| BenchError::connect(anyhow!("Subscriber thread ended")))?
+        .map_err(BenchError::connect)?;
+    return Ok(ChannelReceiver { receiver, running });
+}
+
+struct ChannelReceiver {
+    receiver: mpsc::Receiver<BenchResult<MsgType>>,
+    /// Ends the subscriber thread.
+    running: Arc<AtomicBool>,
+}
+
+impl Receiver for ChannelReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(result) => result.map(Some),
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(BenchError::connect(anyhow!("Subscriber thread ended"))),
+        }
+    }
+}
+
+impl Drop for ChannelReceiver {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+    }
+}
+
+struct StreamSender {
+    con: Connection,
+    key: String,
+    max_len: usize,
+}
+
+impl Sender for StreamSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        return streams::add(&mut self.con, &self.key, self.max_len, &msg)
+            .map_err(|e| redis_error(e, BenchError::send));
+    }
+}
+
+/// Reads the echoed entries as consumer group, acknowledging them right away.
+struct StreamReceiver {
+    con: Connection,
+    key: String,
+    group: String,
+    entries: VecDeque<MsgType>,
+}
+
+impl Receiver for StreamReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        if self.entries.is_empty() {
+            let block_ms = timeout.as_millis() as usize;
+            let entries = streams::read_group(&mut self.con, &self.key, &self.group, "bench", READ_COUNT, block_ms)
+                .map_err(|e| redis_error(e, BenchError::receive))?;
+
+            let ids: Vec<String> = entries.iter().map(|(id, _)| id.clone()).collect();
+            streams::ack(&mut self.con, &self.key, &self.group, &ids)
+                .map_err(|e| redis_error(e, BenchError::receive))?;
+            self.entries.extend(entries.into_iter().map(|(_, data)| data));
+        }
+
+        return Ok(self.entries.pop_front());
+    }
+}
+
+/// Lost connections end the step, anything else becomes `other`.
+fn redis_error(e: RedisError, other: fn(anyhow::Error) -> BenchError) -> BenchError {
+    if e.is_connection_dropped() || e.is_connection_refusal() {
+        return BenchError::connect(e);
+    }
+    return other(e.into());
+}
+
+fn run_bench(config: &RedisConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let con_send = streams::connect(&config.address).map_err(BenchError::connect)?;
+    let mut con_recv = streams::connect(&config.address).map_err(BenchError::connect)?;
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    match config.mode {
+        RedisMode::PubSub => {
+            let recv = subscribe(con_recv, config.channel_recv.clone())?;
+            let send = PubSubSender { con: con_send, channel: config.channel_send.clone() };
+            return bench.run(send, recv);
+        }
+        RedisMode::Streams => {
+            streams::create_group(&mut con_recv, &config.channel_recv, &config.group).map_err(BenchError::connect)?;
+            let send = StreamSender {
+                con: con_send,
+                key: config.channel_send.clone(),
+                max_len: config.max_len,
+            };
+            let recv = StreamReceiver {
+                con: con_recv,
+                key: config.channel_recv.clone(),
+                group: config.group.clone(),
+                entries: VecDeque::new(),
+            };
+            return bench.run(send, recv);
+        }
+    }
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.redis.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_broker(Readiness::Listening(config.redis.address.clone())).unwrap();
+        supervisor.spawn_echo(
+            "redis-echo",
+            &[
+                &config.redis.address,
+                config.redis.mode.name(),
+                &config.redis.channel_send,
+                &config.redis.channel_recv,
+                &config.redis.group,
+                &config.redis.max_len.to_string(),
+            ],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.redis.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.redis,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.redis.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
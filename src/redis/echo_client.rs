@@ -0,0 +1,90 @@
+use anyhow::Result;
+use redis::{Commands, Connection};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+mod streams;
+
+// Time out regularly to notice the exit request
+const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+const READ_COUNT: usize = 100;
+
+/// Publishes every message of `channel_send` on `channel_recv`.
+fn echo_pub_sub(mut subscriber: Connection, mut publisher: Connection, channel_send: &str, channel_recv: &str, running: &AtomicBool) -> Result<()> {
+    let mut pubsub = subscriber.as_pubsub();
+    pubsub.subscribe(channel_send)?;
+    pubsub.set_read_timeout(Some(RECV_TIMEOUT))?;
+
+    println!("Waiting for messages..");
+    while running.load(Ordering::SeqCst) {
+        let msg = match pubsub.get_message() {
+            Ok(msg) => msg,
+            Err(e) if e.is_timeout() => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let published: redis::RedisResult<usize> = publisher.publish(channel_recv, msg.get_payload_bytes());
+        if let Err(e) = published {
+            println!("Publish error: {e}");
+        }
+    }
+
+    return Ok(());
+}
+
+/// Reads `stream_send` as consumer group and adds every entry to `stream_recv`.
+fn echo_streams(mut con: Connection, stream_send: &str, stream_recv: &str, group: &str, max_len: usize, running: &AtomicBool) -> Result<()> {
+    // Entries added before the echo came up are not echoed
+    streams::create_group(&mut con, stream_send, group)?;
+
+    println!("Waiting for messages..");
+    while running.load(Ordering::SeqCst) {
+        let block_ms = RECV_TIMEOUT.as_millis() as usize;
+        let entries = match streams::read_group(&mut con, stream_send, group, "echo", READ_COUNT, block_ms) {
+            Ok(entries) => entries,
+            Err(e) if e.is_timeout() => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut ids = Vec::with_capacity(entries.len());
+        for (id, data) in entries {
+            if let Err(e) = streams::add(&mut con, stream_recv, max_len, &data) {
+                println!("XADD error: {e}");
+            }
+            ids.push(id);
+        }
+        if let Err(e) = streams::ack(&mut con, stream_send, group, &ids) {
+            println!("XACK error: {e}");
+        }
+    }
+
+    return Ok(());
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |idx: usize, default: &str| args.get(idx).cloned().unwrap_or(default.to_string());
+
+    let addr = arg(1, "127.0.0.1:6379");
+    let mode = arg(2, "pub_sub");
+    let channel_send = arg(3, "redis_send");
+    let channel_recv = arg(4, "redis_recv");
+    let group = arg(5, "bench");
+    let max_len = arg(6, "100000").parse()?;
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let con = streams::connect(&addr)?;
+    match mode.as_str() {
+        "streams" => echo_streams(con, &channel_send, &channel_recv, &group, max_len, &running)?,
+        _ => echo_pub_sub(con, streams::connect(&addr)?, &channel_send, &channel_recv, &running)?,
+    }
+
+    println!("Shutting down");
+    return Ok(());
+}
@@ -0,0 +1,56 @@
+use redis::streams::{StreamMaxlen, StreamReadOptions, StreamReadReply};
+use redis::{Commands, Connection, RedisResult};
+
+/// Field of the stream entries holding the message.
+const FIELD_DATA: &str = "data";
+
+pub fn connect(addr: &str) -> RedisResult<Connection> {
+    let url = format!("redis://{addr}/");
+    println!("Connecting to Redis server at {url}");
+    return redis::Client::open(url)?.get_connection();
+}
+
+/// Creates the consumer group, starting after the newest entry.
+///
+/// An existing group is moved to the newest entry as well, so entries left over
+/// from an earlier run are not read again.
+pub fn create_group(con: &mut Connection, key: &str, group: &str) -> RedisResult<()> {
+    let created: RedisResult<()> = con.xgroup_create_mkstream(key, group, "$");
+    match created {
+        Err(e) if e.code() == Some("BUSYGROUP") => con.xgroup_setid(key, group, "$"),
+        result => result,
+    }
+}
+
+pub fn add(con: &mut Connection, key: &str, max_len: usize, data: &[u8]) -> RedisResult<()> {
+    return con.xadd_maxlen(key, StreamMaxlen::Approx(max_len), "*", &[(FIELD_DATA, data)]);
+}
+
+/// Reads up to `count` new entries for `consumer`, blocking for at most `block_ms`.
+///
+/// Returns the entry ids, which still have to be acknowledged, with their data.
+pub fn read_group(con: &mut Connection, key: &str, group: &str, consumer: &str, count: usize, block_ms: usize) -> RedisResult<Vec<(String, Vec<u8>)>> {
+    // Blocking for 0 ms would block forever
+    let options = StreamReadOptions::default()
+        .group(group, consumer)
+        .count(count)
+        .block(block_ms.max(1));
+    let reply: Option<StreamReadReply> = con.xread_options(&[key], &[">"], &options)?;
+
+    let entries = reply.into_iter()
+        .flat_map(|reply| reply.keys)
+        .flat_map(|stream| stream.ids)
+        .map(|entry| {
+            let data = entry.get(FIELD_DATA).unwrap_or_default();
+            (entry.id, data)
+        })
+        .collect();
+    return Ok(entries);
+}
+
+pub fn ack(con: &mut Connection, key: &str, group: &str, ids: &[String]) -> RedisResult<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    return con.xack(key, group, ids);
+}
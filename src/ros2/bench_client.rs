@@ -1,98 +1,143 @@
-use futures::executor::LocalPool;
-use futures::{future, Stream};
-use futures::stream::StreamExt;
-use futures::task::LocalSpawnExt;
+use futures::{FutureExt, Stream, StreamExt};
 use r2r::{QosProfile, Node, Publisher};
 use r2r::std_msgs::msg;
-use std::sync::{Arc, Mutex};
-
-use std::time::{Duration, Instant};
+use std::pin::Pin;
+use std::time::Duration;
 
 use anyhow::Result;
 
 #[path="../benchmarker.rs"]
 mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path="../config.rs"]
+mod config;
+use config::{Config, Ros2Config};
 
-use benchmarker::{ClientStats, BenchSettings};
-use benchmarker::run_benchmark;
+#[path="../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
 
-fn ros2_init_sub() -> (Node, impl Stream<Item = msg::String> + 'static) {
-    let ctx = r2r::Context::create().unwrap();
-    let mut node = r2r::Node::create(ctx, "ros2_sub", "").unwrap();
+struct Ros2Sender {
+    publisher: Publisher<msg::UInt8MultiArray>,
+    // Node needs to be kept to prevent it from being dropped
+    _node: Node,
+}
 
-    let topic_rsp = "ros2_rsp";
-    let subscriber = node.subscribe::<r2r::std_msgs::msg::String>(topic_rsp, QosProfile::default()).unwrap();
+impl Ros2Sender {
+    pub fn new() -> BenchResult<Self> {
+        let ctx = r2r::Context::create().map_err(BenchError::connect)?;
+        let mut node = r2r::Node::create(ctx, "ros2_pub", "").map_err(BenchError::connect)?;
 
-    return (node, subscriber);
+        let topic_req = "ros2_req";
+
+        let publisher = node.create_publisher::<msg::UInt8MultiArray>(topic_req, QosProfile::default())
+            .map_err(BenchError::protocol)?;
+        Ok(Self {
+            publisher,
+            _node: node,
+        })
+    }
 }
 
-fn ros2_listen(node_sub: (Node, impl Stream<Item = msg::String> + 'static), duration: Duration) -> Result<ClientStats> {
-    let (mut node, subscriber) = node_sub;
+impl Sender for Ros2Sender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let message = msg::UInt8MultiArray{ data: msg, ..Default::default() };
+        return self.publisher.publish(&message).map_err(BenchError::send);
+    }
+}
 
-    let mut pool = LocalPool::new();
-    let spawner = pool.spawner();
+struct Ros2Receiver {
+    node: Node,
+    subscriber: Pin<Box<dyn Stream<Item = msg::UInt8MultiArray> + Send>>,
+}
 
-    let num_errors = 0;
-    let time_start = Instant::now();
+impl Ros2Receiver {
+    pub fn new() -> BenchResult<Self> {
+        let ctx = r2r::Context::create().map_err(BenchError::connect)?;
+        let mut node = r2r::Node::create(ctx, "ros2_sub", "").map_err(BenchError::connect)?;
 
-    println!("Waiting for messages..");
+        let topic_rsp = "ros2_rsp";
+        let subscriber = node.subscribe::<msg::UInt8MultiArray>(topic_rsp, QosProfile::default())
+            .map_err(BenchError::protocol)?;
 
-    let stats = Arc::new(Mutex::new((0, Instant::now())));
-    let stats_clone = Arc::clone(&stats);
-    spawner.spawn_local(async move {
-        subscriber.for_each(|_msg| {
-            let mut s = stats_clone.lock().unwrap();
-            s.0 += 1;
-            s.1 = Instant::now();
-            return future::ready(());
-        }).await;
-    })?;
+        Ok(Self {
+            node,
+            subscriber: Box::pin(subscriber),
+        })
+    }
 
-    while time_start.elapsed() < duration {
-        node.spin_once(std::time::Duration::from_millis(100));
-        pool.run_until_stalled();
+    fn take(&mut self) -> Option<MsgType> {
+        // Messages are only delivered to the stream while the node spins
+        return self.subscriber.next().now_or_never().flatten().map(|msg| msg.data);
     }
+}
 
-    let duration = time_start.elapsed();
-    let s = stats.lock().unwrap();
+impl Receiver for Ros2Receiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        if let Some(msg) = self.take() {
+            return Ok(Some(msg));
+        }
 
-    return Ok(ClientStats{
-        num: s.0,
-        num_errors,
-        duration,
-        time_last_msg: s.1,
-    });
+        self.node.spin_once(timeout);
+        return Ok(self.take());
+    }
 }
 
-fn ros2_init_pub() -> (Publisher<msg::String>, Node) {
-    let ctx = r2r::Context::create().unwrap();
-    let mut node = r2r::Node::create(ctx, "ros2_pub", "").unwrap();
+fn run_bench(config: &Ros2Config, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
 
-    let topic_req = "ros2_req";
+    let send = Ros2Sender::new()?;
+    let recv = Ros2Receiver::new()?;
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
 
-    let publisher = node.create_publisher::<r2r::std_msgs::msg::String>(topic_req, QosProfile::default()).unwrap();
-    // Node needs to be passed on to prevent it from being dropped
-    return (publisher, node);
-}
-
-fn ros2_send(publ: &mut (Publisher<msg::String>, Node), message: String) -> Result<()> {
-    let (publisher, _node) = publ;
-    let message = msg::String{ data: message };
-    return Ok(publisher.publish(&message)?);
+    return bench.run(send, recv);
 }
 
 fn main() -> Result<()> {
-    let settings = BenchSettings{
-        fn_init_send: ros2_init_pub,
-        fn_init_listen: ros2_init_sub,
-        fn_send: ros2_send,
-        fn_listen: ros2_listen,
-        duration: Duration::from_secs(5),
-        msgs_per_sec: 10.,
-        message_len: 10,
-        out_file: "data/ros2.json".to_string(),
-    };
-
-    run_benchmark(settings);
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml")?
+    )?;
+
+    let _supervisor = config.ros2.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo("ros2-echo", &[], Readiness::Output("Waiting for messages..")).unwrap();
+        supervisor
+    });
+
+    let schedule = config.ros2.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.ros2,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.ros2.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+
     return Ok(());
 }
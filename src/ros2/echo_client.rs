@@ -3,6 +3,8 @@ use futures::future;
 use futures::stream::StreamExt;
 use futures::task::LocalSpawnExt;
 use r2r::QosProfile;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let ctx = r2r::Context::create()?;
@@ -14,8 +16,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut pool = LocalPool::new();
     let spawner = pool.spawner();
 
-    let publisher = node.create_publisher::<r2r::std_msgs::msg::String>(topic_rsp, QosProfile::default())?;
-    let subscriber = node.subscribe::<r2r::std_msgs::msg::String>(topic_req, QosProfile::default())?;
+    let publisher = node.create_publisher::<r2r::std_msgs::msg::UInt8MultiArray>(topic_rsp, QosProfile::default())?;
+    let subscriber = node.subscribe::<r2r::std_msgs::msg::UInt8MultiArray>(topic_req, QosProfile::default())?;
 
     spawner.spawn_local(async move {
         subscriber.for_each(|msg| {
@@ -25,8 +27,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .await
     })?;
 
-    loop {
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    })?;
+
+    println!("Waiting for messages..");
+    while running.load(Ordering::SeqCst) {
         node.spin_once(std::time::Duration::from_millis(100));
         pool.run_until_stalled();
     }
+
+    println!("Shutting down");
+    return Ok(());
 }
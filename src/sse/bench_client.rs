@@ -0,0 +1,322 @@
+use anyhow::{anyhow, bail};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::{Bytes, Incoming};
+use hyper::client::conn::http1;
+use hyper::header::{ACCEPT, HOST};
+use hyper::Request;
+use hyper_util::rt::TokioIo;
+use std::io::{prelude::*, ErrorKind};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, SseConfig};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+#[path = "../http/message.rs"]
+mod message;
+
+mod event;
+use event::Event;
+
+const PATH_PUBLISH: &str = "/publish";
+const PATH_EVENTS: &str = "/events";
+
+/// Event data is text, so the messages travel hex encoded.
+fn encode_hex(msg: &[u8]) -> String {
+    return msg.iter().map(|byte| format!("{byte:02x}")).collect();
+}
+
+fn decode_hex(data: &str) -> anyhow::Result<MsgType> {
+    if !data.len().is_multiple_of(2) {
+        bail!("Odd length of hex data");
+    }
+    // Working on bytes, as slicing the text could split a character the server sent
+    return data.as_bytes().chunks(2)
+        .map(|pair| anyhow::Ok(u8::from_str_radix(std::str::from_utf8(pair)?, 16)?))
+        .collect();
+}
+
+/// Posts every message on a persistent connection and waits for the server to accept it.
+///
+/// A lost connection only fails the message, the next one connects again, so a
+/// restarting server does not end the step.
+struct PublishSender {
+    stream: Option<TcpStream>,
+    buffer: Vec<u8>,
+    address: String,
+    response_timeout: Duration,
+}
+
+impl PublishSender {
+    fn connect(address: &str) -> std::io::Result<TcpStream> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        return Ok(stream);
+    }
+
+    fn publish(&mut self, msg: MsgType) -> BenchResult<()> {
+        let stream = match self.stream.as_mut() {
+            Some(stream) => stream,
+            None => self.stream.insert(Self::connect(&self.address).map_err(BenchError::send)?),
+        };
+
+        let request = message::encode_request(&self.address, PATH_PUBLISH, encode_hex(&msg).as_bytes(), false);
+        stream.write_all(&request).map_err(BenchError::send)?;
+
+        let time_start = Instant::now();
+        loop {
+            if message::decode_response(&mut self.buffer).map_err(BenchError::protocol)?.is_some() {
+                return Ok(());
+            }
+
+            let remaining = self.response_timeout.saturating_sub(time_start.elapsed());
+            if remaining.is_zero() {
+                return Err(BenchError::timeout(anyhow!("No response to publish")));
+            }
+            stream.set_read_timeout(Some(remaining)).map_err(BenchError::send)?;
+
+            let mut chunk = [0u8; 1024];
+            match stream.read(&mut chunk) {
+                Ok(0) => return Err(BenchError::send(anyhow!("disconnected"))),
+                Ok(size) => self.buffer.extend_from_slice(&chunk[..size]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(BenchError::send(e)),
+            }
+        }
+    }
+}
+
+impl Sender for PublishSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let result = self.publish(msg);
+        if result.is_err() {
+            // The connection is in an unknown state, a late response would be taken for the next message
+            self.stream = None;
+            self.buffer.clear();
+        }
+        return result;
+    }
+}
+
+/// Follows the event stream and resumes it with `Last-Event-ID` whenever it ends.
+struct EventReceiver {
+    runtime: Runtime,
+    address: String,
+    body: Option<Incoming>,
+    buffer: String,
+    last_event_id: Option<String>,
+    retry: Duration,
+    /// Set while the stream is down, reconnecting is given up after `reconnect_timeout`.
+    time_disconnected: Option<Instant>,
+    time_next_attempt: Instant,
+    reconnect_timeout: Duration,
+    num_reconnects: usize,
+    num_failed_reconnects: usize,
+}
+
+impl EventReceiver {
+    fn connect(&mut self) -> anyhow::Result<()> {
+        let mut request = Request::get(PATH_EVENTS)
+            .header(HOST, &self.address)
+            .header(ACCEPT, "text/event-stream");
+        if let Some(last_event_id) = &self.last_event_id {
+            request = request.header("Last-Event-ID", last_event_id);
+        }
+        let request = request.body(Empty::<Bytes>::new())?;
+
+        let response = self.runtime.block_on(async {
+            let stream = tokio::net::TcpStream::connect(&self.address).await?;
+            stream.set_nodelay(true)?;
+            let (mut sender, connection) = http1::handshake(TokioIo::new(stream)).await?;
+            tokio::spawn(connection);
+            return anyhow::Ok(sender.send_request(request).await?);
+        })?;
+
+        if !response.status().is_success() {
+            bail!("Unexpected status {}", response.status());
+        }
+
+        // An incomplete event of the previous stream is never dispatched
+        self.buffer.clear();
+        self.body = Some(response.into_body());
+        return Ok(());
+    }
+}
+
+impl Receiver for EventReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let time_start = Instant::now();
+        loop {
+            if let Some(event) = Event::decode(&mut self.buffer) {
+                if let Some(retry) = event.retry {
+                    self.retry = Duration::from_millis(retry);
+                }
+                if event.id.is_some() {
+                    self.last_event_id = event.id;
+                }
+                if event.data.is_empty() {
+                    continue;
+                }
+                return decode_hex(&event.data).map(Some).map_err(BenchError::protocol);
+            }
+
+            let remaining = timeout.saturating_sub(time_start.elapsed());
+            let Some(body) = self.body.as_mut() else {
+                let wait = self.time_next_attempt.saturating_duration_since(Instant::now());
+                if wait > remaining {
+                    std::thread::sleep(remaining);
+                    return Ok(None);
+                }
+                std::thread::sleep(wait);
+
+                match self.connect() {
+                    Ok(()) => {
+                        self.time_disconnected = None;
+                        self.num_reconnects += 1;
+                    }
+                    Err(e) => {
+                        let time_disconnected = *self.time_disconnected.get_or_insert_with(Instant::now);
+                        if time_disconnected.elapsed() > self.reconnect_timeout {
+                            return Err(BenchError::connect(e));
+                        }
+                        self.num_failed_reconnects += 1;
+                        self.time_next_attempt = Instant::now() + self.retry;
+                    }
+                }
+                continue;
+            };
+
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+
+            let next = self.runtime.block_on(async {
+                return tokio::time::timeout(remaining, body.frame()).await;
+            });
+
+            match next {
+                Ok(Some(Ok(frame))) => {
+                    if let Ok(data) = frame.into_data() {
+                        self.buffer.push_str(&String::from_utf8_lossy(&data));
+                    }
+                }
+                // The stream ended, it is resumed after the retry delay
+                Ok(Some(Err(_))) | Ok(None) => {
+                    self.body = None;
+                    self.time_disconnected = Some(Instant::now());
+                    self.time_next_attempt = Instant::now() + self.retry;
+                }
+                Err(_) => return Ok(None),
+            }
+        }
+    }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) {
+        if self.num_reconnects > 0 {
+            println!("Event stream resumed {} times", self.num_reconnects);
+        }
+        if self.num_failed_reconnects > 0 {
+            println!("Reconnecting failed {} times", self.num_failed_reconnects);
+        }
+    }
+}
+
+fn run_bench(config: &SseConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let runtime = Runtime::new().map_err(BenchError::connect)?;
+
+    let mut recv = EventReceiver {
+        runtime,
+        address: config.address.clone(),
+        body: None,
+        buffer: String::new(),
+        last_event_id: None,
+        retry: Duration::from_millis(config.retry_ms),
+        time_disconnected: None,
+        time_next_attempt: Instant::now(),
+        reconnect_timeout: Duration::from_secs_f64(config.schedule.drain.grace_secs),
+        num_reconnects: 0,
+        num_failed_reconnects: 0,
+    };
+    recv.connect().map_err(BenchError::connect)?;
+
+    let stream = PublishSender::connect(&config.address).map_err(BenchError::connect)?;
+    let send = PublishSender {
+        stream: Some(stream),
+        buffer: Vec::new(),
+        address: config.address.clone(),
+        response_timeout: Duration::from_secs_f64(config.schedule.drain.grace_secs),
+    };
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    return bench.run(send, recv);
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.sse.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "sse-echo",
+            &[
+                &config.sse.address,
+                &config.sse.history_size.to_string(),
+                &config.sse.reconnect_after.unwrap_or(0).to_string(),
+                &config.sse.retry_ms.to_string(),
+            ],
+            Readiness::Listening(config.sse.address.clone()),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.sse.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.sse,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.sse.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+use tokio::sync::broadcast;
+use tokio::time::timeout;
+
+mod event;
+use event::Event;
+
+// Time out regularly to notice the exit request
+const ACCEPT_TIMEOUT: Duration = Duration::from_millis(100);
+
+const PATH_PUBLISH: &str = "/publish";
+const PATH_EVENTS: &str = "/events";
+
+type Body = BoxBody<Bytes, Infallible>;
+
+/// Numbers the published events and keeps the latest ones for reconnecting clients.
+struct Broadcast {
+    next_id: u64,
+    history: VecDeque<(u64, String)>,
+    history_size: usize,
+    sender: broadcast::Sender<(u64, String)>,
+}
+
+struct Settings {
+    reconnect_after: Option<usize>,
+    retry_ms: u64,
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Full::default().boxed());
+    *response.status_mut() = code;
+    return response;
+}
+
+async fn publish(request: Request<Incoming>, broadcast: Arc<Mutex<Broadcast>>) -> Result<Response<Body>, hyper::Error> {
+    let body = request.into_body().collect().await?.to_bytes();
+    let Ok(data) = String::from_utf8(body.to_vec()) else {
+        return Ok(status(StatusCode::BAD_REQUEST));
+    };
+
+    let mut broadcast = broadcast.lock().unwrap();
+    let id = broadcast.next_id;
+    broadcast.next_id += 1;
+
+    broadcast.history.push_back((id, data.clone()));
+    if broadcast.history.len() > broadcast.history_size {
+        broadcast.history.pop_front();
+    }
+    // Nobody listening is fine, the event is still kept for replay
+    let _ = broadcast.sender.send((id, data));
+
+    return Ok(status(StatusCode::OK));
+}
+
+/// Opens an event stream, replaying the kept events after `Last-Event-ID` first.
+fn subscribe(request: Request<Incoming>, broadcast: Arc<Mutex<Broadcast>>, settings: Arc<Settings>) -> Response<Body> {
+    let last_event_id: Option<u64> = request.headers().get("Last-Event-ID")
+        .and_then(|id| id.to_str().ok())
+        .and_then(|id| id.parse().ok());
+
+    // Subscribing and taking the replay under the same lock leaves no gap between them
+    let (replay, receiver) = {
+        let broadcast = broadcast.lock().unwrap();
+        let replay: VecDeque<(u64, String)> = match last_event_id {
+            Some(last_event_id) => broadcast.history.iter()
+                .filter(|(id, _)| *id > last_event_id)
+                .cloned()
+                .collect(),
+            None => VecDeque::new(),
+        };
+        (replay, broadcast.sender.subscribe())
+    };
+    if let Some(last_event_id) = last_event_id {
+        println!("Client resumed after event {last_event_id}, replaying {}", replay.len());
+    }
+
+    let retry = Event { retry: Some(settings.retry_ms), ..Default::default() };
+    let reconnect_after = settings.reconnect_after;
+    let events = stream::unfold((replay, receiver, 0), move |(mut replay, mut receiver, sent)| async move {
+        if reconnect_after.is_some_and(|reconnect_after| sent >= reconnect_after) {
+            return None;
+        }
+
+        let (id, data) = match replay.pop_front() {
+            Some(event) => event,
+            // A lagging client misses events, closing makes it resume from the history
+            None => receiver.recv().await.ok()?,
+        };
+        let event = Event { id: Some(id.to_string()), data, retry: None };
+        return Some((event, (replay, receiver, sent + 1)));
+    });
+
+    let frames = stream::once(async move { retry })
+        .chain(events)
+        .map(|event| Ok(Frame::data(Bytes::from(event.encode()))));
+
+    let mut response = Response::new(BodyExt::boxed(StreamBody::new(frames)));
+    response.headers_mut().insert(CONTENT_TYPE, "text/event-stream".parse().unwrap());
+    response.headers_mut().insert(CACHE_CONTROL, "no-cache".parse().unwrap());
+    return response;
+}
+
+async fn handle(request: Request<Incoming>, broadcast: Arc<Mutex<Broadcast>>, settings: Arc<Settings>) -> Result<Response<Body>, hyper::Error> {
+    match (request.method(), request.uri().path()) {
+        (&Method::POST, PATH_PUBLISH) => publish(request, broadcast).await,
+        (&Method::GET, PATH_EVENTS) => Ok(subscribe(request, broadcast, settings)),
+        _ => Ok(status(StatusCode::NOT_FOUND)),
+    }
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let addr_default = "127.0.0.1:8081".to_string();
+    let addr = args.get(1).unwrap_or(&addr_default);
+    let history_size = args.get(2).and_then(|size| size.parse().ok()).unwrap_or(1024);
+    // 0 keeps the streams open
+    let reconnect_after = args.get(3).and_then(|after| after.parse().ok()).filter(|after| *after > 0);
+    let retry_ms = args.get(4).and_then(|retry| retry.parse().ok()).unwrap_or(100);
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let (sender, _) = broadcast::channel(history_size.max(1));
+    let broadcast = Arc::new(Mutex::new(Broadcast {
+        next_id: 0,
+        history: VecDeque::with_capacity(history_size),
+        history_size,
+        sender,
+    }));
+    let settings = Arc::new(Settings { reconnect_after, retry_ms });
+
+    let runtime = Runtime::new()?;
+    runtime.block_on(async {
+        let listener = TcpListener::bind(addr).await?;
+        let builder = auto::Builder::new(TokioExecutor::new());
+
+        println!("Waiting for messages..");
+        while running.load(Ordering::SeqCst) {
+            let (stream, _) = match timeout(ACCEPT_TIMEOUT, listener.accept()).await {
+                Ok(Ok(connection)) => connection,
+                Ok(Err(e)) => {
+                    println!("Accept error: {e}");
+                    continue;
+                }
+                Err(_) => continue,
+            };
+            stream.set_nodelay(true)?;
+
+            let builder = builder.clone();
+            let broadcast = broadcast.clone();
+            let settings = settings.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| handle(request, broadcast.clone(), settings.clone()));
+                if let Err(e) = builder.serve_connection(TokioIo::new(stream), service).await {
+                    println!("Connection error: {e}");
+                }
+            });
+        }
+
+        return anyhow::Ok(());
+    })?;
+
+    println!("Shutting down");
+    return Ok(());
+}
@@ -0,0 +1,50 @@
+/// A server-sent event with the fields the benchmark uses.
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    pub id: Option<String>,
+    pub data: String,
+    /// Reconnection delay in milliseconds the client should use from now on.
+    pub retry: Option<u64>,
+}
+
+impl Event {
+    pub fn encode(&self) -> String {
+        let mut event = String::new();
+        if let Some(retry) = self.retry {
+            event.push_str(&format!("retry: {retry}\n"));
+        }
+        if let Some(id) = &self.id {
+            event.push_str(&format!("id: {id}\n"));
+        }
+        for line in self.data.lines() {
+            event.push_str(&format!("data: {line}\n"));
+        }
+        event.push('\n');
+        return event;
+    }
+
+    /// Takes the first complete event off the front of `buffer`.
+    ///
+    /// Events end with an empty line, comments and unknown fields are skipped.
+    pub fn decode(buffer: &mut String) -> Option<Self> {
+        let normalized = buffer.replace("\r\n", "\n");
+        let end = normalized.find("\n\n")?;
+
+        let mut event = Event::default();
+        let mut data: Vec<&str> = Vec::new();
+        for line in normalized[..end].lines() {
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "id" => event.id = Some(value.to_string()),
+                "data" => data.push(value),
+                "retry" => event.retry = value.parse().ok(),
+                _ => {}
+            }
+        }
+        event.data = data.join("\n");
+
+        *buffer = normalized[end + 2..].to_string();
+        return Some(event);
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{anyhow, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::benchmarker::{track_child_group, untrack_child_group};
+use crate::config::SpawnConfig;
+
+/// How the supervisor decides that a spawned process is ready to serve the benchmark.
+pub enum Readiness {
+    /// The process accepts TCP connections on the given address.
+    Listening(String),
+    /// The process printed a line containing the given text.
+    Output(&'static str),
+}
+
+struct Supervised {
+    name: String,
+    child: Child,
+    log: File,
+}
+
+/// Owns the echo servers and brokers started for a benchmark and tears them down on drop.
+pub struct Supervisor {
+    config: SpawnConfig,
+    procs: Vec<Supervised>,
+}
+
+impl Supervisor {
+    pub fn new(config: &SpawnConfig) -> Result<Self> {
+        std::fs::create_dir_all(&config.log_dir)?;
+
+        Ok(Self {
+            config: config.clone(),
+            procs: Vec::new(),
+        })
+    }
+
+    /// Starts the broker command from the config, if there is one.
+    pub fn spawn_broker(&mut self, ready: Readiness) -> Result<()> {
+        let Some(cmd) = self.config.broker.clone() else {
+            return Ok(());
+        };
+
+        let mut parts = cmd.split_whitespace();
+        let program = parts.next().ok_or(anyhow!("Empty broker command"))?;
+        let mut command = Command::new(program);
+        command.args(parts);
+
+        self.spawn("broker", command, ready)
+    }
+
+    /// Starts the echo binary which was built next to the running bench binary.
+    pub fn spawn_echo(&mut self, name: &str, args: &[&str], ready: Readiness) -> Result<()> {
+        if !self.config.echo {
+            return Ok(());
+        }
+
+        let mut command = Command::new(std::env::current_exe()?.with_file_name(name));
+        command.args(args);
+
+        self.spawn(name, command, ready)
+    }
+
+    fn spawn(&mut self, name: &str, mut command: Command, ready: Readiness) -> Result<()> {
+        let log_path = PathBuf::from(&self.config.log_dir).join(format!("{name}.log"));
+        let log = OpenOptions::new().create(true).append(true).open(&log_path)?;
+
+        println!("Starting {name}, logging to {}", log_path.display());
+        // A separate process group keeps Ctrl+C in the terminal away from the children,
+        // they are stopped by `shutdown` once the bench has finished its step
+        let mut child = command
+            .process_group(0)
+            .stdout(Stdio::piped())
+            .stderr(log.try_clone()?)
+            .spawn()?;
+        // The child leads its new group, so its pid is the group id
+        track_child_group(child.id() as libc::pid_t);
+
+        // Copy the output to the log file while watching for the readiness line
+        let stdout = child.stdout.take().ok_or(anyhow!("No stdout for {name}"))?;
+        let mut log_out = log.try_clone()?;
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                let _ = writeln!(log_out, "{line}");
+                let _ = tx.send(line);
+            }
+        });
+
+        self.procs.push(Supervised {
+            name: name.to_string(),
+            child,
+            log,
+        });
+
+        self.wait_ready(ready, rx)
+    }
+
+    fn wait_ready(&mut self, ready: Readiness, lines: mpsc::Receiver<String>) -> Result<()> {
+        let proc = self.procs.last_mut().unwrap();
+        let timeout = Duration::from_secs(self.config.ready_timeout_secs);
+        let time_start = Instant::now();
+
+        loop {
+            if let Some(status) = proc.child.try_wait()? {
+                return Err(anyhow!("{} exited before becoming ready: {status}", proc.name));
+            }
+
+            if time_start.elapsed() > timeout {
+                return Err(anyhow!("{} not ready after {timeout:?}", proc.name));
+            }
+
+            let is_ready = match &ready {
+                Readiness::Listening(addr) => TcpStream::connect(addr).is_ok(),
+                Readiness::Output(text) => lines.try_iter().any(|line| line.contains(text)),
+            };
+
+            if is_ready {
+                println!("{} ready", proc.name);
+                return Ok(());
+            }
+
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// Interrupts all processes in reverse start order and records their exit status.
+    pub fn shutdown(&mut self) {
+        while let Some(mut proc) = self.procs.pop() {
+            // The process group, so a broker started through a shell is interrupted too
+            let pid = proc.child.id() as libc::pid_t;
+            let interrupted = unsafe { libc::kill(-pid, libc::SIGINT) } == 0;
+            if !interrupted {
+                println!("Interrupting {} failed: {}", proc.name, std::io::Error::last_os_error());
+            }
+
+            let time_start = Instant::now();
+            let status = loop {
+                match proc.child.try_wait() {
+                    Ok(Some(status)) => break Some(status),
+                    // Killed right away if it could not be interrupted
+                    Ok(None) if interrupted && time_start.elapsed() < Duration::from_secs(5) => {
+                        std::thread::sleep(Duration::from_millis(50));
+                    }
+                    _ => {
+                        unsafe { libc::kill(-pid, libc::SIGKILL) };
+                        let _ = proc.child.kill();
+                        break proc.child.wait().ok();
+                    }
+                }
+            };
+
+            let status = match status {
+                Some(status) => status.to_string(),
+                None => "unknown".to_string(),
+            };
+            untrack_child_group(pid);
+            println!("{} stopped with {status}", proc.name);
+            let _ = writeln!(proc.log, "-- {} stopped with {status}", proc.name);
+        }
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
@@ -1,15 +1,20 @@
-use anyhow::Result;
-use std::io::prelude::*;
+use anyhow::anyhow;
+use std::io::{prelude::*, ErrorKind};
 use std::time::Instant;
 use std::{net::TcpStream, time::Duration};
 
 #[path = "../benchmarker.rs"]
 mod benchmarker;
-use benchmarker::{index_from_message, Benchmarker, MsgType, Receiver, Sender};
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
 
 #[path = "../config.rs"]
 mod config;
-use config::Config;
+use config::{Config, TcpConfig};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
 
 struct WsSender {
     stream: TcpStream,
@@ -22,106 +27,112 @@ impl WsSender {
 }
 
 impl Sender for WsSender {
-    fn send(&mut self, msg: MsgType) -> Result<()> {
-        self.stream.write_all(&msg)?;
-        Ok(())
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        self.stream.write_all(&msg).map_err(BenchError::send)
     }
 }
 
 struct WsReceiver {
     stream: TcpStream,
-    num_messages: usize,
-    duration: Duration,
+    buffer: Vec<u8>,
     message_size: usize,
 }
 
 impl WsReceiver {
-    pub fn new(
-        stream: TcpStream,
-        num_messages: usize,
-        duration: Duration,
-        message_size: usize,
-    ) -> Self {
+    pub fn new(stream: TcpStream, message_size: usize) -> Self {
         Self {
             stream,
-            num_messages,
-            duration,
+            buffer: Vec::with_capacity(message_size),
             message_size,
         }
     }
 }
 
 impl Receiver for WsReceiver {
-    fn listen(&mut self) -> Result<Vec<Option<Instant>>> {
-        let mut msgs = vec![None; self.num_messages];
-
-        let mut num_received = 0;
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        // The stream has no framing, so reads are collected until a full message is buffered
         let time_start = Instant::now();
-        loop {
-            if num_received == self.num_messages {
-                break;
-            }
-
-            if time_start.elapsed() > self.duration + Duration::from_secs(5) {
-                break;
+        while self.buffer.len() < self.message_size {
+            let remaining = timeout.saturating_sub(time_start.elapsed());
+            if remaining.is_zero() {
+                return Ok(None);
             }
-
-            let mut msg = vec![0u8; self.message_size*8 + 8];
-            let Ok(msg_size) = self.stream.read(&mut msg) else {
-                continue;
-            };
-
-            if msg_size == 0 {
-                println!("disconnected");
-                break;
+            self.stream.set_read_timeout(Some(remaining)).map_err(BenchError::receive)?;
+
+            let mut chunk = vec![0u8; self.message_size];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(BenchError::connect(anyhow!("disconnected"))),
+                Ok(msg_size) => self.buffer.extend_from_slice(&chunk[..msg_size]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None);
+                }
+                Err(e) => return Err(BenchError::receive(e)),
             }
-
-            num_received += 1;
-
-            let Ok(idx) = index_from_message(msg) else {
-                println!("parse error");
-                continue;
-            };
-            msgs[idx] = Some(Instant::now());
         }
 
-        return Ok(msgs);
+        let rest = self.buffer.split_off(self.message_size);
+        return Ok(Some(std::mem::replace(&mut self.buffer, rest)));
     }
 }
 
-fn run_bench(addr: &String, num_messages: usize, duration: Duration, message_size: usize) {
-    let stream = TcpStream::connect(addr).unwrap();
+fn run_bench(config: &TcpConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let stream = TcpStream::connect(&config.address).map_err(BenchError::connect)?;
     // Increase size to add  message number
-    let message_size = message_size + 8;
-    stream.set_nonblocking(true).unwrap();
+    let message_size = config.message_size + 8;
     println!("connected");
 
-    let send = WsSender::new(stream.try_clone().unwrap());
-    let recv = WsReceiver::new(stream.try_clone().unwrap(), num_messages, duration, message_size);
+    let send = WsSender::new(stream.try_clone().map_err(BenchError::connect)?);
+    let recv = WsReceiver::new(stream.try_clone().map_err(BenchError::connect)?, message_size);
     let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
 
-    let stats = bench.run(send, recv);
-    dbg!(&stats);
+    return bench.run(send, recv);
     //stream.shutdown(std::net::Shutdown::Both).unwrap();
 }
 
 fn main() {
+    handle_interrupt();
+
     let config: Config = toml::from_str(
         &std::fs::read_to_string("config.toml").unwrap()
     ).unwrap();
 
-    let schedule = config.tcp.schedule;
+    let _supervisor = config.tcp.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "tcp-echo",
+            &[&config.tcp.address],
+            Readiness::Listening(config.tcp.address.clone()),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.tcp.schedule.clone();
 
     let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
     for i in 0..schedule.steps {
         let duration = Duration::from_secs(schedule.secs_per_step);
         let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
 
-        run_bench(
-            &config.tcp.address,
+        let result = run_bench(
+            &config.tcp,
             num_messages.floor() as usize, 
             duration, 
-            config.tcp.message_size,
         );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.tcp.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
     }
 }
@@ -1,6 +1,9 @@
-use std::io::prelude::*;
+use std::io::{prelude::*, ErrorKind};
 use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread::spawn;
+use std::time::Duration;
 
 fn main() {
     let args: Vec<String> = std::env::args()
@@ -10,10 +13,30 @@ fn main() {
     let addr = args.get(1).unwrap_or(&addr_default).to_string();
     let message_size = 5*8 + 8;
 
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
     let server_recv = TcpListener::bind(addr).unwrap();
-    for stream in server_recv.incoming() {
+    // Accept without blocking to notice the exit request
+    server_recv.set_nonblocking(true).unwrap();
+    while running.load(Ordering::SeqCst) {
+        let mut stream = match server_recv.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            Err(e) => {
+                println!("Accept error: {e}");
+                continue;
+            }
+        };
+        stream.set_nonblocking(false).unwrap();
+
         spawn(move || {
-            let mut stream = stream.unwrap();
             loop {
                 let mut msg = vec![0u8; message_size];
                 let Ok(msg_size) = stream.read(&mut msg) else {
@@ -25,10 +48,12 @@ fn main() {
                     break;
                 }
 
-                stream.write_all(&msg).unwrap();
+                stream.write_all(&msg[..msg_size]).unwrap();
             }
 
             println!("disconnected");
         });
     }
+
+    println!("Shutting down");
 }
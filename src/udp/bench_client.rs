@@ -0,0 +1,135 @@
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, UdpConfig};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod socket;
+use socket::set_buffer_sizes;
+
+const MAX_DATAGRAM: usize = 65535;
+
+struct UdpSender {
+    socket: UdpSocket,
+}
+
+impl UdpSender {
+    pub fn new(socket: UdpSocket) -> Self {
+        Self { socket }
+    }
+}
+
+impl Sender for UdpSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        // One message per datagram, so there is no framing to take care of
+        return self.socket.send(&msg).map(|_| ()).map_err(BenchError::send);
+    }
+}
+
+struct UdpReceiver {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+}
+
+impl UdpReceiver {
+    pub fn new(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            buffer: vec![0; MAX_DATAGRAM],
+        }
+    }
+}
+
+impl Receiver for UdpReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let timeout = timeout.max(Duration::from_millis(1));
+        self.socket.set_read_timeout(Some(timeout)).map_err(BenchError::receive)?;
+
+        match self.socket.recv(&mut self.buffer) {
+            Ok(len) => Ok(Some(self.buffer[..len].to_vec())),
+            // The echo server is not (yet) listening, which the probe takes care of
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => Ok(None),
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => Ok(None),
+            Err(e) => Err(BenchError::receive(e)),
+        }
+    }
+}
+
+fn run_bench(config: &UdpConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(BenchError::connect)?;
+    socket.connect(&config.address).map_err(BenchError::connect)?;
+    set_buffer_sizes(&socket, config.recv_buffer_size, config.send_buffer_size)
+        .map_err(BenchError::connect)?;
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let send = UdpSender::new(socket.try_clone().map_err(BenchError::connect)?);
+    let recv = UdpReceiver::new(socket);
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    return bench.run(send, recv);
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.udp.spawn.as_ref().map(|spawn| {
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(
+            "udp-echo",
+            &[
+                &config.udp.address,
+                &config.udp.recv_buffer_size.unwrap_or(0).to_string(),
+                &config.udp.send_buffer_size.unwrap_or(0).to_string(),
+            ],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.udp.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.udp,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.udp.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
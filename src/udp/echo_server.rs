@@ -0,0 +1,51 @@
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+mod socket;
+use socket::set_buffer_sizes;
+
+/// Parses an optional buffer size argument, where 0 keeps the OS default.
+fn buffer_size_arg(arg: Option<&String>) -> Option<usize> {
+    return arg.and_then(|size| size.parse().ok()).filter(|size| *size > 0);
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let addr_default = "127.0.0.1:3031".to_string();
+    let addr = args.get(1).unwrap_or(&addr_default).to_string();
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let socket = UdpSocket::bind(&addr).unwrap();
+    set_buffer_sizes(&socket, buffer_size_arg(args.get(2)), buffer_size_arg(args.get(3))).unwrap();
+    // Time out regularly to notice the exit request
+    socket.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+
+    println!("Waiting for messages..");
+    let mut buffer = vec![0u8; 65535];
+    while running.load(Ordering::SeqCst) {
+        let (len, peer) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted) => continue,
+            Err(e) => {
+                println!("Receive error: {e}");
+                continue;
+            }
+        };
+
+        if let Err(e) = socket.send_to(&buffer[..len], peer) {
+            println!("Send error: {e}");
+        }
+    }
+
+    println!("Shutting down");
+}
@@ -0,0 +1,58 @@
+use std::io::{Error, Result};
+use std::net::UdpSocket;
+use std::os::fd::AsRawFd;
+
+fn set_option(socket: &UdpSocket, option: libc::c_int, value: usize) -> Result<()> {
+    let value = value as libc::c_int;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if ret != 0 {
+        return Err(Error::last_os_error());
+    }
+    return Ok(());
+}
+
+fn get_option(socket: &UdpSocket, option: libc::c_int) -> Result<usize> {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    if ret != 0 {
+        return Err(Error::last_os_error());
+    }
+    return Ok(value as usize);
+}
+
+/// Applies the configured buffer sizes and prints the ones in effect,
+/// which the kernel may double or cap at `net.core.rmem_max`/`wmem_max`.
+pub fn set_buffer_sizes(socket: &UdpSocket, recv: Option<usize>, send: Option<usize>) -> Result<()> {
+    if let Some(size) = recv {
+        set_option(socket, libc::SO_RCVBUF, size)?;
+    }
+    if let Some(size) = send {
+        set_option(socket, libc::SO_SNDBUF, size)?;
+    }
+
+    println!(
+        "Socket buffers: recv {} bytes, send {} bytes",
+        get_option(socket, libc::SO_RCVBUF)?,
+        get_option(socket, libc::SO_SNDBUF)?,
+    );
+    return Ok(());
+}
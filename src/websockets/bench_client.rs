@@ -1,34 +1,60 @@
-use std::{net::TcpStream, time::Duration};
-use tungstenite::{connect, Message, WebSocket, stream::MaybeTlsStream};
-use std::time::Instant;
-use anyhow::Result;
+use std::{io::ErrorKind, net::TcpStream, time::{Duration, Instant}};
+use tungstenite::{connect, Message, WebSocket, stream::MaybeTlsStream, protocol::Role};
+use anyhow::anyhow;
 
 #[path="../benchmarker.rs"]
 mod benchmarker;
-use benchmarker::{Benchmarker, Sender, Receiver, MsgType, index_from_message};
+use benchmarker::{Benchmarker, Sender, Receiver, MsgType, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
 
 #[path="../config.rs"]
 mod config;
-use config::Config;
+use config::{Config, WebsocketConfig, WebsocketProtocol};
+
+#[path="../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod stomp;
+use stomp::Frame;
+
+const STOMP_SUBSCRIPTION: &str = "0";
+const SETUP_TIMEOUT: Duration = Duration::from_secs(5);
 
 type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
 
+/// Opens a websocket connection and returns two handles to it, one for sending and one for receiving.
+///
+/// The echo answers on the same connection, so both sides share one TCP stream.
+fn ws_connect(addr: &String) -> BenchResult<(Socket, Socket)> {
+    let (socket, response) =
+        connect(addr).map_err(BenchError::connect)?;
+
+    println!("Connected to the server");
+    println!("Response HTTP code: {}", response.status());
+    println!("Response contains the following headers:");
+    for (ref header, _value) in response.headers() {
+        println!("* {}", header);
+    }
+
+    let MaybeTlsStream::Plain(stream) = socket.get_ref() else {
+        return Err(BenchError::connect(anyhow!("TLS connections are not supported")));
+    };
+    let socket_recv = WebSocket::from_raw_socket(
+        MaybeTlsStream::Plain(stream.try_clone().map_err(BenchError::connect)?),
+        Role::Client,
+        None,
+    );
+
+    return Ok((socket, socket_recv));
+}
+
 struct WsSender {
     socket: Socket,
 }
 
 impl WsSender {
-    pub fn new(addr: &String) -> Self {
-        let (socket, response) =
-            connect(addr).expect("Can't connect");
-
-        println!("Connected to the server");
-        println!("Response HTTP code: {}", response.status());
-        println!("Response contains the following headers:");
-        for (ref header, _value) in response.headers() {
-            println!("* {}", header);
-        }
-
+    pub fn new(socket: Socket) -> Self {
         Self{
             socket
         }
@@ -36,9 +62,8 @@ impl WsSender {
 }
 
 impl Sender for WsSender {
-    fn send(&mut self, msg: MsgType) -> Result<()> {
-        self.socket.send(Message::Binary(msg))?;
-        Ok(())
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        self.socket.send(Message::Binary(msg)).map_err(|e| ws_error(e, BenchError::Send))
     }
 }
 
@@ -51,93 +76,233 @@ impl Drop for WsSender {
 
 struct WsReceiver {
     socket: Socket,
-    num_messages: usize,
-    duration: Duration,
 }
 
 impl WsReceiver {
-    pub fn new(addr: &String, num_messages: usize, duration: Duration) -> Self {
-        let (socket, response) =
-            connect(addr).expect("Can't connect");
-
-        println!("Connected to the server");
-        println!("Response HTTP code: {}", response.status());
-        println!("Response contains the following headers:");
-        for (ref header, _value) in response.headers() {
-            println!("* {}", header);
-        }
-
+    pub fn new(socket: Socket) -> Self {
         Self{
             socket,
-            num_messages,
-            duration
         }
     }
 }
 
 impl Receiver for WsReceiver {
-    fn listen(&mut self) -> Result<Vec<Option<Instant>>> {
-        let mut msgs = vec![None; self.num_messages];
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        if let MaybeTlsStream::Plain(stream) = self.socket.get_mut() {
+            stream.set_read_timeout(Some(timeout)).map_err(BenchError::receive)?;
+        }
+
+        // `read` will return if connection is closed
+        match self.socket.read() {
+            // We do not expect ping/pong messages to be echoed.
+            Ok(msg) if msg.is_binary() || msg.is_text() => Ok(Some(msg.into_data())),
+            Ok(_) => Ok(None),
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(ws_error(e, BenchError::Receive)),
+        }
+    }
+}
 
-        let mut num_received = 0;
+/// Sorts websocket errors into connection and protocol errors, anything else becomes `other`.
+fn ws_error(e: tungstenite::Error, other: fn(anyhow::Error) -> BenchError) -> BenchError {
+    match e {
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => BenchError::connect(e),
+        tungstenite::Error::Protocol(_) | tungstenite::Error::Capacity(_) => BenchError::protocol(e),
+        _ => other(e.into()),
+    }
+}
+
+/// Wraps every message into a STOMP SEND frame.
+struct StompSender {
+    ws: WsSender,
+    destination: String,
+    receipts: bool,
+    num_sent: usize,
+}
+
+impl Sender for StompSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let mut frame = Frame::new("SEND").header("destination", &self.destination);
+        if self.receipts {
+            self.num_sent += 1;
+            frame = frame.header("receipt", &self.num_sent.to_string());
+        }
+        self.ws.send(frame.body(msg).encode())
+    }
+}
+
+impl Drop for StompSender {
+    fn drop(&mut self) {
+        let _ = self.ws.send(Frame::new("DISCONNECT").encode());
+    }
+}
+
+/// Unwraps the MESSAGE frames and counts the receipts arriving in between.
+struct StompReceiver {
+    ws: WsReceiver,
+    num_receipts: usize,
+}
+
+impl StompReceiver {
+    /// Reads the next frame, skipping heart-beats.
+    fn next_frame(&mut self, timeout: Duration) -> BenchResult<Option<Frame>> {
         let time_start = Instant::now();
         loop {
-            if num_received == self.num_messages {
-                break;
+            let remaining = timeout.saturating_sub(time_start.elapsed());
+            if remaining.is_zero() {
+                return Ok(None);
             }
+            let Some(data) = self.ws.recv(remaining)? else {
+                return Ok(None);
+            };
 
-            if time_start.elapsed() > self.duration + Duration::from_secs(5) {
-                break;
+            if let Some(frame) = Frame::decode(&data).map_err(BenchError::protocol)? {
+                if frame.command == "ERROR" {
+                    let message = frame.get("message").unwrap_or_default();
+                    return Err(BenchError::protocol(anyhow!("Error frame: {message}")));
+                }
+                return Ok(Some(frame));
             }
+        }
+    }
 
-            // `read` will return if connection is closed
-            let Ok(msg) = self.socket.read() else {
-                dbg!("err");
-                continue;
-            };
+    /// Waits for a frame with the given command during the setup.
+    fn expect_frame(&mut self, command: &str) -> BenchResult<Frame> {
+        match self.next_frame(SETUP_TIMEOUT)? {
+            Some(frame) if frame.command == command => Ok(frame),
+            Some(frame) => Err(BenchError::connect(anyhow!("Expected {command}, got {}", frame.command))),
+            None => Err(BenchError::connect(anyhow!("No {command} frame from the server"))),
+        }
+    }
+}
 
-            num_received += 1;
+impl Receiver for StompReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let time_start = Instant::now();
+        loop {
+            let remaining = timeout.saturating_sub(time_start.elapsed());
+            let Some(frame) = self.next_frame(remaining)? else {
+                return Ok(None);
+            };
 
-            dbg!(&msg);
+            match frame.command.as_str() {
+                "MESSAGE" => return Ok(Some(frame.body)),
+                "RECEIPT" => self.num_receipts += 1,
+                command => return Err(BenchError::protocol(anyhow!("Unexpected {command} frame"))),
+            }
+        }
+    }
+}
 
-            let Ok(idx) = index_from_message(msg.into_data()) else {
-                continue;
-            };
-            msgs[idx] = Some(Instant::now());
+impl Drop for StompReceiver {
+    fn drop(&mut self) {
+        if self.num_receipts > 0 {
+            println!("Received {} receipts", self.num_receipts);
         }
+    }
+}
 
-        dbg!(&msgs);
-        _ = self.socket.close(None);
+/// Opens the STOMP session and subscribes to the destination the messages are sent to.
+fn stomp_connect(config: &WebsocketConfig, send: &mut WsSender, recv: &mut StompReceiver) -> BenchResult<()> {
+    let addr = socket_addr(&config.address);
+    let host = addr.split(':').next().unwrap_or(&addr);
 
-        return Ok(msgs);
-    }
+    let connect = Frame::new("CONNECT")
+        .header("accept-version", "1.2")
+        .header("host", host)
+        .header("heart-beat", "0,0");
+    send.send(connect.encode())?;
+    recv.expect_frame("CONNECTED")?;
+
+    // The receipt tells that no message can get lost to a missing subscription
+    let subscribe = Frame::new("SUBSCRIBE")
+        .header("id", STOMP_SUBSCRIPTION)
+        .header("destination", &config.destination)
+        .header("ack", "auto")
+        .header("receipt", "subscribed");
+    send.send(subscribe.encode())?;
+    recv.expect_frame("RECEIPT")?;
+
+    return Ok(());
 }
 
-fn run_bench(addr: &String, num_messages: usize, duration: Duration, message_size: usize) {
-    let send = WsSender::new(addr);
-    let recv = WsReceiver::new(addr, num_messages, duration);
-    let mut bench = Benchmarker::new(num_messages, duration, message_size+8);
+fn run_bench(config: &WebsocketConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let (socket_send, socket_recv) = ws_connect(&config.address)?;
+    let mut send = WsSender::new(socket_send);
+    let recv = WsReceiver::new(socket_recv);
+    let mut bench = Benchmarker::new(num_messages, duration, config.message_size+8);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    match config.protocol {
+        WebsocketProtocol::Raw => {
+            return bench.run(send, recv);
+        }
+        WebsocketProtocol::Stomp => {
+            let mut recv = StompReceiver { ws: recv, num_receipts: 0 };
+            stomp_connect(config, &mut send, &mut recv)?;
+
+            let send = StompSender {
+                ws: send,
+                destination: config.destination.clone(),
+                receipts: config.receipts,
+                num_sent: 0,
+            };
+            return bench.run(send, recv);
+        }
+    }
+}
 
-    bench.run(send, recv);
+/// Extracts `host:port` from a websocket url like `ws://localhost:9001/socket`.
+fn socket_addr(url: &str) -> String {
+    let addr = url.split_once("://").map_or(url, |(_, rest)| rest);
+    return addr.split('/').next().unwrap_or(addr).to_string();
 }
 
 fn main() {
+    handle_interrupt();
+
     let config: Config = toml::from_str(
         &std::fs::read_to_string("config.toml").unwrap()
     ).unwrap();
 
-    let schedule = config.websocket.schedule;
+    let _supervisor = config.websocket.spawn.as_ref().map(|spawn| {
+        let addr = socket_addr(&config.websocket.address);
+        let echo = match config.websocket.protocol {
+            WebsocketProtocol::Raw => "websocket-echo",
+            WebsocketProtocol::Stomp => "stomp-echo",
+        };
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo(echo, &[&addr], Readiness::Listening(addr.clone())).unwrap();
+        supervisor
+    });
+
+    let schedule = config.websocket.schedule.clone();
 
     let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
     for i in 0..schedule.steps {
         let duration = Duration::from_secs(schedule.secs_per_step);
         let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
 
-        run_bench(
-            &config.websocket.address,
+        let result = run_bench(
+            &config.websocket,
             num_messages.floor() as usize, 
             duration, 
-            config.websocket.message_size,
         );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.websocket.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
     }
 }
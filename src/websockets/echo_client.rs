@@ -1,18 +1,56 @@
+use std::io::ErrorKind;
 use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread::spawn;
+use std::time::Duration;
 use tungstenite::accept;
 
 fn main () {
-    let server_recv = TcpListener::bind("127.0.0.1:9001").unwrap();
-    for stream in server_recv.incoming() {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let addr_default = "127.0.0.1:9001".to_string();
+    let addr = args.get(1).unwrap_or(&addr_default).to_string();
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let server_recv = TcpListener::bind(addr).unwrap();
+    // Accept without blocking to notice the exit request
+    server_recv.set_nonblocking(true).unwrap();
+    while running.load(Ordering::SeqCst) {
+        let stream = match server_recv.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            Err(e) => {
+                println!("Accept error: {e}");
+                continue;
+            }
+        };
+        stream.set_nonblocking(false).unwrap();
+
         spawn (move || {
-            let mut websocket = accept(stream.unwrap()).unwrap();
+            // The supervisor's readiness probe connects without a handshake
+            let mut websocket = match accept(stream) {
+                Ok(websocket) => websocket,
+                Err(e) => {
+                    println!("Handshake error: {e}");
+                    return;
+                }
+            };
             loop {
-                //let Ok(msg) = websocket.read() else {
-                //    println!("Read error");
-                //    break;
-                //};
-                let msg = websocket.read().unwrap();
+                // Fails once the client closed the connection
+                let Ok(msg) = websocket.read() else {
+                    println!("Read error");
+                    break;
+                };
 
                 // We do not want to send back ping/pong messages.
                 if msg.is_binary() || msg.is_text() {
@@ -22,4 +60,6 @@ fn main () {
             }
         });
     }
+
+    println!("Shutting down");
 }
@@ -0,0 +1,210 @@
+use anyhow::{anyhow, bail, Result};
+
+/// A STOMP 1.2 frame, carried in a single websocket message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub command: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(command: &str) -> Self {
+        Self { command: command.to_string(), headers: Vec::new(), body: Vec::new() }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        return self;
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.body = body;
+        return self;
+    }
+
+    /// The first value of a header, repeated headers are ignored as the spec demands.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        return self.headers.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str());
+    }
+
+    /// The CONNECT and CONNECTED frames do not escape their headers.
+    fn escapes_headers(&self) -> bool {
+        self.command != "CONNECT" && self.command != "CONNECTED"
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(self.command.len() + self.body.len() + 64);
+        frame.extend_from_slice(self.command.as_bytes());
+        frame.push(b'\n');
+
+        for (name, value) in &self.headers {
+            if self.escapes_headers() {
+                frame.extend_from_slice(escape(name).as_bytes());
+                frame.push(b':');
+                frame.extend_from_slice(escape(value).as_bytes());
+            } else {
+                frame.extend_from_slice(format!("{name}:{value}").as_bytes());
+            }
+            frame.push(b'\n');
+        }
+        // The body is binary, so the receiver cannot look for the NUL
+        if !self.body.is_empty() {
+            frame.extend_from_slice(format!("content-length:{}\n", self.body.len()).as_bytes());
+        }
+
+        frame.push(b'\n');
+        frame.extend_from_slice(&self.body);
+        frame.push(0);
+        return frame;
+    }
+
+    /// Decodes a frame, returns `None` for heart-beats which consist of EOLs only.
+    pub fn decode(data: &[u8]) -> Result<Option<Self>> {
+        let start = data.iter().position(|&byte| byte != b'\n' && byte != b'\r');
+        let Some(start) = start else {
+            return Ok(None);
+        };
+        let data = &data[start..];
+
+        // Each line may end in LF or CRLF, the first empty line ends the headers
+        let mut head = Vec::new();
+        let mut pos = 0;
+        let body_start = loop {
+            let Some(eol) = data[pos..].iter().position(|&byte| byte == b'\n') else {
+                bail!("Frame without end of headers");
+            };
+            let line = &data[pos..pos + eol];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            pos += eol + 1;
+            if line.is_empty() {
+                break pos;
+            }
+            head.push(std::str::from_utf8(line)?);
+        };
+
+        let mut lines = head.into_iter();
+        let command = lines.next().ok_or(anyhow!("Frame without command"))?.to_string();
+
+        let mut frame = Self::new(&command);
+        for line in lines {
+            let (name, value) = line.split_once(':').ok_or(anyhow!("Invalid header {line:?}"))?;
+            if frame.escapes_headers() {
+                frame.headers.push((unescape(name)?, unescape(value)?));
+            } else {
+                frame.headers.push((name.to_string(), value.to_string()));
+            }
+        }
+
+        let rest = &data[body_start..];
+        let body_len = match frame.get("content-length") {
+            Some(length) => length.parse::<usize>()?,
+            None => rest.iter().position(|&byte| byte == 0).ok_or(anyhow!("Frame without NUL"))?,
+        };
+        if rest.get(body_len) != Some(&0) {
+            bail!("Body does not match content-length {body_len}");
+        }
+        frame.body = rest[..body_len].to_vec();
+
+        return Ok(Some(frame));
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            ':' => escaped.push_str("\\c"),
+            _ => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+fn unescape(text: &str) -> Result<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('c') => unescaped.push(':'),
+            other => bail!("Invalid escape sequence \\{other:?}"),
+        }
+    }
+    return Ok(unescaped);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let frames = [
+            Frame::new("CONNECT").header("accept-version", "1.2").header("host", "localhost"),
+            Frame::new("SEND").header("destination", "/queue/a:b\\c\nd").body(vec![0, b'\n', b'\n', 0]),
+            Frame::new("MESSAGE").header("subscription", "0").body(b"text".to_vec()),
+            Frame::new("RECEIPT").header("receipt-id", "subscribed"),
+        ];
+
+        for frame in frames {
+            let mut decoded = Frame::decode(&frame.encode()).unwrap().unwrap();
+            // The content-length is added for bodies
+            decoded.headers.retain(|(name, _)| name != "content-length");
+            assert_eq!(decoded, frame);
+        }
+    }
+
+    #[test]
+    fn connect_headers_not_escaped() {
+        let frame = Frame::new("CONNECTED").header("server", "a\\c");
+        assert_eq!(frame.encode(), b"CONNECTED\nserver:a\\c\n\n\0");
+        assert_eq!(Frame::decode(&frame.encode()).unwrap().unwrap(), frame);
+    }
+
+    #[test]
+    fn crlf() {
+        let frame = Frame::decode(b"\r\nSEND\r\ndestination:/queue/a\r\n\r\nbody\n\nmore\0").unwrap().unwrap();
+        assert_eq!(frame.command, "SEND");
+        assert_eq!(frame.get("destination"), Some("/queue/a"));
+        assert_eq!(frame.body, b"body\n\nmore");
+
+        // Line endings may be mixed
+        let frame = Frame::decode(b"SEND\r\ndestination:/queue/a\n\r\nbody\0").unwrap().unwrap();
+        assert_eq!(frame.get("destination"), Some("/queue/a"));
+        assert_eq!(frame.body, b"body");
+    }
+
+    #[test]
+    fn repeated_header() {
+        let frame = Frame::decode(b"MESSAGE\nfoo:first\nfoo:second\n\n\0").unwrap().unwrap();
+        assert_eq!(frame.get("foo"), Some("first"));
+    }
+
+    #[test]
+    fn heart_beat() {
+        assert_eq!(Frame::decode(b"\n").unwrap(), None);
+        assert_eq!(Frame::decode(b"\r\n\r\n").unwrap(), None);
+    }
+
+    #[test]
+    fn invalid_frames() {
+        assert!(Frame::decode(b"SEND\ndestination:a").is_err());
+        assert!(Frame::decode(b"SEND\ndestination\n\n\0").is_err());
+        assert!(Frame::decode(b"SEND\ndestination:a\\x\n\n\0").is_err());
+        assert!(Frame::decode(b"SEND\n\nbody").is_err());
+        assert!(Frame::decode(b"SEND\ncontent-length:2\n\nbody\0").is_err());
+        assert!(Frame::decode(b"SEND\ncontent-length:x\n\n\0").is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::Result;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::spawn;
+use std::time::Duration;
+use tungstenite::{accept, Message, WebSocket};
+
+mod stomp;
+use stomp::Frame;
+
+/// A minimal STOMP broker per connection, messages sent to a destination
+/// go back to the subscriptions of the same connection.
+struct Session {
+    socket: WebSocket<TcpStream>,
+    /// Subscription ids with their destination.
+    subscriptions: Vec<(String, String)>,
+    next_message_id: u64,
+}
+
+impl Session {
+    fn send(&mut self, frame: Frame) -> Result<()> {
+        self.socket.send(Message::Binary(frame.encode()))?;
+        return Ok(());
+    }
+
+    /// Answers a frame, returns false once the connection should end.
+    fn handle(&mut self, frame: Frame) -> Result<bool> {
+        match frame.command.as_str() {
+            "CONNECT" | "STOMP" => {
+                let connected = Frame::new("CONNECTED")
+                    .header("version", "1.2")
+                    .header("heart-beat", "0,0");
+                self.send(connected)?;
+            }
+            "SUBSCRIBE" => {
+                let (Some(id), Some(destination)) = (frame.get("id"), frame.get("destination")) else {
+                    return self.error("SUBSCRIBE needs id and destination");
+                };
+                self.subscriptions.push((id.to_string(), destination.to_string()));
+            }
+            "UNSUBSCRIBE" => {
+                let id = frame.get("id").unwrap_or_default().to_string();
+                self.subscriptions.retain(|(subscription, _)| *subscription != id);
+            }
+            "SEND" => {
+                let Some(destination) = frame.get("destination") else {
+                    return self.error("SEND needs a destination");
+                };
+
+                let matching: Vec<String> = self.subscriptions.iter()
+                    .filter(|(_, subscribed)| subscribed == destination)
+                    .map(|(id, _)| id.clone())
+                    .collect();
+                for id in matching {
+                    self.next_message_id += 1;
+                    let message = Frame::new("MESSAGE")
+                        .header("subscription", &id)
+                        .header("message-id", &self.next_message_id.to_string())
+                        .header("destination", destination)
+                        .body(frame.body.clone());
+                    self.send(message)?;
+                }
+            }
+            "DISCONNECT" => {
+                self.send_receipt(&frame)?;
+                return Ok(false);
+            }
+            command => {
+                return self.error(&format!("Unsupported command {command}"));
+            }
+        }
+
+        self.send_receipt(&frame)?;
+        return Ok(true);
+    }
+
+    fn send_receipt(&mut self, frame: &Frame) -> Result<()> {
+        let Some(receipt) = frame.get("receipt") else {
+            return Ok(());
+        };
+        let receipt = Frame::new("RECEIPT").header("receipt-id", receipt);
+        return self.send(receipt);
+    }
+
+    /// The spec closes the connection after an ERROR frame.
+    fn error(&mut self, message: &str) -> Result<bool> {
+        println!("Error: {message}");
+        self.send(Frame::new("ERROR").header("message", message))?;
+        return Ok(false);
+    }
+}
+
+fn serve(stream: TcpStream) {
+    let socket = match accept(stream) {
+        Ok(socket) => socket,
+        Err(e) => {
+            println!("Handshake error: {e}");
+            return;
+        }
+    };
+    let mut session = Session { socket, subscriptions: Vec::new(), next_message_id: 0 };
+
+    loop {
+        // Fails once the client closed the connection
+        let Ok(msg) = session.socket.read() else {
+            println!("Read error");
+            break;
+        };
+        if !msg.is_binary() && !msg.is_text() {
+            continue;
+        }
+
+        let frame = match Frame::decode(&msg.into_data()) {
+            Ok(Some(frame)) => frame,
+            // Heart-beat
+            Ok(None) => continue,
+            Err(e) => {
+                let _ = session.error(&format!("Invalid frame: {e}"));
+                break;
+            }
+        };
+        match session.handle(frame) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                println!("Send error: {e}");
+                break;
+            }
+        }
+    }
+
+    let _ = session.socket.close(None);
+    let _ = session.socket.flush();
+}
+
+fn main () {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let addr_default = "127.0.0.1:9001".to_string();
+    let addr = args.get(1).unwrap_or(&addr_default).to_string();
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let server_recv = TcpListener::bind(addr).unwrap();
+    // Accept without blocking to notice the exit request
+    server_recv.set_nonblocking(true).unwrap();
+    while running.load(Ordering::SeqCst) {
+        let stream = match server_recv.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            Err(e) => {
+                println!("Accept error: {e}");
+                continue;
+            }
+        };
+        stream.set_nonblocking(false).unwrap();
+
+        spawn(move || serve(stream));
+    }
+
+    println!("Shutting down");
+}
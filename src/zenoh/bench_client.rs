@@ -0,0 +1,187 @@
+use anyhow::anyhow;
+use std::sync::mpsc;
+use std::time::Duration;
+use zenoh::handlers::FifoChannelHandler;
+use zenoh::pubsub::{Publisher, Subscriber};
+use zenoh::sample::Sample;
+use zenoh::{Session, Wait};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, ZenohConfig, ZenohPattern, ZenohQosConfig};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+mod qos;
+mod session;
+
+struct ZenohSender {
+    publisher: Publisher<'static>,
+}
+
+impl Sender for ZenohSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        return self.publisher.put(msg).wait().map_err(|e| BenchError::send(anyhow!(e)));
+    }
+}
+
+struct ZenohReceiver {
+    subscriber: Subscriber<FifoChannelHandler<Sample>>,
+}
+
+impl Receiver for ZenohReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        let sample = self.subscriber.recv_timeout(timeout)
+            .map_err(|e| BenchError::connect(anyhow!(e)))?;
+        return Ok(sample.map(|sample| sample.payload().to_bytes().to_vec()));
+    }
+}
+
+/// Sends every message as query, the replies arrive through a callback.
+struct QuerySender {
+    session: Session,
+    key_query: String,
+    qos: ZenohQosConfig,
+    tx: mpsc::Sender<MsgType>,
+}
+
+impl Sender for QuerySender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        let tx = self.tx.clone();
+        return self.session.get(&self.key_query)
+            .congestion_control(qos::congestion_control(&self.qos))
+            .priority(qos::priority(&self.qos))
+            .express(self.qos.express)
+            .payload(msg)
+            .callback(move |reply| {
+                if let Ok(sample) = reply.result() {
+                    let _ = tx.send(sample.payload().to_bytes().to_vec());
+                }
+            })
+            .wait()
+            .map_err(|e| BenchError::send(anyhow!(e)));
+    }
+}
+
+struct QueryReceiver {
+    rx: mpsc::Receiver<MsgType>,
+}
+
+impl Receiver for QueryReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(msg) => Ok(Some(msg)),
+            // The sender holds a handle as well, so this only times out
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+fn run_bench(config: &ZenohConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    let session = session::open(config.mode.name(), &config.endpoints, false)
+        .map_err(BenchError::connect)?;
+
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    let result = match config.pattern {
+        ZenohPattern::PubSub => {
+            let publisher = session.declare_publisher(config.key_req.clone())
+                .congestion_control(qos::congestion_control(&config.qos))
+                .priority(qos::priority(&config.qos))
+                .express(config.qos.express)
+                .reliability(qos::reliability(&config.qos))
+                .wait()
+                .map_err(|e| BenchError::protocol(anyhow!(e)))?;
+            let subscriber = session.declare_subscriber(config.key_rsp.clone()).wait()
+                .map_err(|e| BenchError::protocol(anyhow!(e)))?;
+
+            bench.run(ZenohSender { publisher }, ZenohReceiver { subscriber })
+        }
+        ZenohPattern::Query => {
+            let (tx, rx) = mpsc::channel();
+            let send = QuerySender {
+                session: session.clone(),
+                key_query: config.key_query.clone(),
+                qos: config.qos.clone(),
+                tx,
+            };
+            bench.run(send, QueryReceiver { rx })
+        }
+    };
+
+    let _ = session.close().wait();
+    return result;
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.zenoh.spawn.as_ref().map(|spawn| {
+        let endpoints = config.zenoh.endpoints.join(",");
+        // The router is up once it accepts connections on the first tcp locator
+        let router_addr = config.zenoh.endpoints.first()
+            .map(|endpoint| endpoint.trim_start_matches("tcp/").to_string())
+            .unwrap_or_default();
+
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_broker(Readiness::Listening(router_addr)).unwrap();
+        supervisor.spawn_echo(
+            "zenoh-echo",
+            &[
+                config.zenoh.mode.name(),
+                config.zenoh.pattern.name(),
+                &endpoints,
+                &toml::to_string(&config.zenoh.qos).unwrap(),
+                &config.zenoh.key_req,
+                &config.zenoh.key_rsp,
+                &config.zenoh.key_query,
+            ],
+            Readiness::Output("Waiting for messages.."),
+        ).unwrap();
+        supervisor
+    });
+
+    let schedule = config.zenoh.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.zenoh,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.zenoh.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use zenoh::Wait;
+
+#[path = "../config.rs"]
+mod config;
+use config::ZenohQosConfig;
+
+mod qos;
+mod session;
+
+// Time out regularly to notice the exit request
+const RECV_TIMEOUT: Duration = Duration::from_millis(100);
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let mode = args.get(1).map(String::as_str).unwrap_or("peer");
+    let pattern = args.get(2).map(String::as_str).unwrap_or("pub_sub");
+    let endpoints: Vec<String> = args.get(3)
+        .map(String::as_str)
+        .unwrap_or("tcp/127.0.0.1:7447")
+        .split(',')
+        .map(String::from)
+        .collect();
+    // The bench hands over its QoS serialized as TOML
+    let qos_config: ZenohQosConfig = args.get(4).map_or(ZenohQosConfig::default(), |qos| toml::from_str(qos).expect("Invalid QoS"));
+    println!("QoS: {qos_config:?}");
+    let key_req = args.get(5).map(String::as_str).unwrap_or("zenoh_req").to_string();
+    let key_rsp = args.get(6).map(String::as_str).unwrap_or("zenoh_rsp").to_string();
+    let key_query = args.get(7).map(String::as_str).unwrap_or("zenoh_query").to_string();
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    // As peer the echo is the one the bench connects to, clients both go through the router
+    let session = session::open(mode, &endpoints, mode == "peer")?;
+
+    match pattern {
+        "query" => {
+            let queryable = session.declare_queryable(key_query).wait().map_err(|e| anyhow!(e))?;
+
+            println!("Waiting for messages..");
+            while running.load(Ordering::SeqCst) {
+                let Ok(Some(query)) = queryable.recv_timeout(RECV_TIMEOUT) else {
+                    continue;
+                };
+
+                let payload = query.payload().map(|payload| payload.to_bytes().to_vec()).unwrap_or_default();
+                if let Err(e) = query.reply(query.key_expr().clone(), payload).wait() {
+                    println!("Reply error: {e}");
+                }
+            }
+        }
+        _ => {
+            let subscriber = session.declare_subscriber(key_req).wait().map_err(|e| anyhow!(e))?;
+            let publisher = session.declare_publisher(key_rsp)
+                .congestion_control(qos::congestion_control(&qos_config))
+                .priority(qos::priority(&qos_config))
+                .express(qos_config.express)
+                .reliability(qos::reliability(&qos_config))
+                .wait()
+                .map_err(|e| anyhow!(e))?;
+
+            println!("Waiting for messages..");
+            while running.load(Ordering::SeqCst) {
+                let Ok(Some(sample)) = subscriber.recv_timeout(RECV_TIMEOUT) else {
+                    continue;
+                };
+
+                if let Err(e) = publisher.put(sample.payload().clone()).wait() {
+                    println!("Publish error: {e}");
+                }
+            }
+        }
+    }
+
+    session.close().wait().map_err(|e| anyhow!(e))?;
+    println!("Shutting down");
+    return Ok(());
+}
@@ -0,0 +1,30 @@
+use zenoh::qos::{CongestionControl, Priority, Reliability};
+
+use crate::config::{ZenohCongestionControl, ZenohPriority, ZenohQosConfig, ZenohReliability};
+
+pub fn congestion_control(config: &ZenohQosConfig) -> CongestionControl {
+    match config.congestion_control {
+        ZenohCongestionControl::Block => CongestionControl::Block,
+        ZenohCongestionControl::Drop => CongestionControl::Drop,
+    }
+}
+
+pub fn priority(config: &ZenohQosConfig) -> Priority {
+    match config.priority {
+        ZenohPriority::RealTime => Priority::RealTime,
+        ZenohPriority::InteractiveHigh => Priority::InteractiveHigh,
+        ZenohPriority::InteractiveLow => Priority::InteractiveLow,
+        ZenohPriority::DataHigh => Priority::DataHigh,
+        ZenohPriority::Data => Priority::Data,
+        ZenohPriority::DataLow => Priority::DataLow,
+        ZenohPriority::Background => Priority::Background,
+    }
+}
+
+/// Only publishers take a reliability, it selects the link rather than adding retransmissions.
+pub fn reliability(config: &ZenohQosConfig) -> Reliability {
+    match config.reliability {
+        ZenohReliability::Reliable => Reliability::Reliable,
+        ZenohReliability::BestEffort => Reliability::BestEffort,
+    }
+}
@@ -0,0 +1,21 @@
+use anyhow::{anyhow, Result};
+use zenoh::{Session, Wait};
+
+/// Opens a session in peer or client mode.
+///
+/// Scouting is disabled, so sessions only find each other through the configured
+/// endpoints, which `listen` makes this session accept connections on.
+pub fn open(mode: &str, endpoints: &[String], listen: bool) -> Result<Session> {
+    let endpoints = serde_json::to_string(endpoints)?;
+
+    let mut config = zenoh::Config::default();
+    config.insert_json5("mode", &format!("\"{mode}\"")).map_err(|e| anyhow!(e))?;
+    config.insert_json5("scouting/multicast/enabled", "false").map_err(|e| anyhow!(e))?;
+    if listen {
+        config.insert_json5("listen/endpoints", &endpoints).map_err(|e| anyhow!(e))?;
+    } else {
+        config.insert_json5("connect/endpoints", &endpoints).map_err(|e| anyhow!(e))?;
+    }
+
+    return zenoh::open(config).wait().map_err(|e| anyhow!(e));
+}
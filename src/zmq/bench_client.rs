@@ -0,0 +1,273 @@
+use anyhow::anyhow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+#[path = "../benchmarker.rs"]
+mod benchmarker;
+use benchmarker::{Benchmarker, MsgType, Receiver, Sender, BenchError, BenchResult, BenchStats};
+use benchmarker::{handle_interrupt, is_interrupted, record_failure};
+
+#[path = "../config.rs"]
+mod config;
+use config::{Config, ZmqConfig, ZmqPattern};
+
+#[path = "../supervisor.rs"]
+mod supervisor;
+use supervisor::{Readiness, Supervisor};
+
+const INPROC_ENDPOINT: &str = "inproc://dealer";
+/// How often the REQ thread checks whether the step is over.
+const STOP_INTERVAL: Duration = Duration::from_millis(100);
+
+fn zmq_error(e: zmq::Error, other: fn(anyhow::Error) -> BenchError) -> BenchError {
+    // The context is gone, nothing will work on this socket anymore
+    if e == zmq::Error::ETERM {
+        return BenchError::connect(e);
+    }
+    return other(e.into());
+}
+
+/// Receives on the socket with a timeout, zmq reports expired timeouts as `EAGAIN`.
+fn recv_timeout(socket: &zmq::Socket, timeout: Duration) -> BenchResult<Option<MsgType>> {
+    let timeout = timeout.as_millis().max(1) as i32;
+    socket.set_rcvtimeo(timeout).map_err(BenchError::receive)?;
+
+    match socket.recv_bytes(0) {
+        Ok(msg) => Ok(Some(msg)),
+        Err(zmq::Error::EAGAIN) => Ok(None),
+        Err(e) => Err(zmq_error(e, BenchError::receive)),
+    }
+}
+
+/// Queues the requests for the thread owning the REQ socket.
+struct ReqSender {
+    requests: mpsc::Sender<MsgType>,
+}
+
+impl Sender for ReqSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        return self.requests.send(msg).map_err(|_| BenchError::connect(anyhow!("Socket thread stopped")));
+    }
+}
+
+/// Hands the replies collected on another thread to the benchmarker.
+struct ChannelReceiver {
+    rx: mpsc::Receiver<(MsgType, Instant)>,
+    // Stops the thread owning the socket, if there is one
+    stop: Arc<AtomicBool>,
+}
+
+impl Receiver for ChannelReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        return Ok(self.recv_timed(timeout)?.map(|(msg, _)| msg));
+    }
+
+    // The replies are timestamped by the socket thread, not when they are taken from the channel
+    fn recv_timed(&mut self, timeout: Duration) -> BenchResult<Option<(MsgType, Instant)>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(received) => Ok(Some(received)),
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(BenchError::connect(anyhow!("Socket thread stopped")))
+            }
+        }
+    }
+}
+
+impl Drop for ChannelReceiver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+struct SocketSender {
+    socket: zmq::Socket,
+}
+
+impl Sender for SocketSender {
+    fn send(&mut self, msg: MsgType) -> BenchResult<()> {
+        return self.socket.send(msg, 0).map_err(|e| zmq_error(e, BenchError::send));
+    }
+}
+
+struct SocketReceiver {
+    socket: zmq::Socket,
+}
+
+impl Receiver for SocketReceiver {
+    fn recv(&mut self, timeout: Duration) -> BenchResult<Option<MsgType>> {
+        return recv_timeout(&self.socket, timeout);
+    }
+}
+
+/// Sends the queued requests one at a time, a REQ socket allows only one outstanding request.
+fn run_req(req: zmq::Socket, requests: mpsc::Receiver<MsgType>, tx: mpsc::Sender<(MsgType, Instant)>, reply_timeout: Duration, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::SeqCst) {
+        let msg = match requests.recv_timeout(STOP_INTERVAL) {
+            Ok(msg) => msg,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        };
+
+        if let Err(e) = req.send(msg, 0) {
+            println!("Send error: {e}");
+            continue;
+        }
+
+        // A missing reply only loses this message, the relaxed socket may send again
+        match recv_timeout(&req, reply_timeout) {
+            Ok(Some(reply)) => {
+                if tx.send((reply, Instant::now())).is_err() {
+                    return;
+                }
+            }
+            Ok(None) => {}
+            Err(e) => println!("Receive error: {e}"),
+        }
+    }
+}
+
+/// Moves messages between the dealer and the sender's pair socket.
+///
+/// Sockets must not be shared between threads, so this thread owns the dealer
+/// and both directions go through it.
+fn run_dealer(dealer: zmq::Socket, pair: zmq::Socket, tx: mpsc::Sender<(MsgType, Instant)>, stop: Arc<AtomicBool>) {
+    while !stop.load(Ordering::SeqCst) {
+        let mut items = [
+            pair.as_poll_item(zmq::POLLIN),
+            dealer.as_poll_item(zmq::POLLIN),
+        ];
+        if zmq::poll(&mut items, 100).is_err() {
+            break;
+        }
+        let (outgoing, incoming) = (items[0].is_readable(), items[1].is_readable());
+
+        if outgoing {
+            while let Ok(msg) = pair.recv_bytes(zmq::DONTWAIT) {
+                if let Err(e) = dealer.send(msg, 0) {
+                    println!("Send error: {e}");
+                }
+            }
+        }
+
+        if incoming {
+            while let Ok(msg) = dealer.recv_bytes(zmq::DONTWAIT) {
+                if tx.send((msg, Instant::now())).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn run_bench(config: &ZmqConfig, num_messages: usize, duration: Duration) -> BenchResult<BenchStats> {
+    // Increase size to add  message number
+    let message_size = config.message_size + 8;
+
+    let mut bench = Benchmarker::new(num_messages, duration, message_size);
+    bench.apply_schedule(&config.schedule);
+    bench.out_file = config.out_file.clone();
+
+    let context = zmq::Context::new();
+    let socket = |kind: zmq::SocketType, endpoint: &str| -> BenchResult<zmq::Socket> {
+        let socket = context.socket(kind).map_err(BenchError::connect)?;
+        // Queued messages must not keep the step from ending
+        socket.set_linger(0).map_err(BenchError::connect)?;
+        socket.connect(endpoint).map_err(BenchError::connect)?;
+        return Ok(socket);
+    };
+
+    match config.pattern {
+        ZmqPattern::ReqRep => {
+            let req = socket(zmq::REQ, &config.endpoint)?;
+            req.set_req_relaxed(true).map_err(BenchError::connect)?;
+            req.set_req_correlate(true).map_err(BenchError::connect)?;
+
+            let (requests_tx, requests) = mpsc::channel();
+            let (tx, rx) = mpsc::channel();
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread_stop = stop.clone();
+            let reply_timeout = Duration::from_secs_f64(config.schedule.drain.grace_secs);
+            std::thread::spawn(move || run_req(req, requests, tx, reply_timeout, thread_stop));
+
+            let send = ReqSender { requests: requests_tx };
+            let recv = ChannelReceiver { rx, stop };
+            return bench.run(send, recv);
+        }
+        ZmqPattern::PubSub => {
+            let endpoint_recv = config.endpoint_recv.as_ref()
+                .ok_or(BenchError::connect(anyhow!("pub_sub needs endpoint_recv")))?;
+
+            let send = SocketSender {
+                socket: socket(zmq::PUB, &config.endpoint)?,
+            };
+            let sub = socket(zmq::SUB, endpoint_recv)?;
+            sub.set_subscribe(b"").map_err(BenchError::connect)?;
+            let recv = SocketReceiver { socket: sub };
+            return bench.run(send, recv);
+        }
+        ZmqPattern::DealerRouter => {
+            let dealer = socket(zmq::DEALER, &config.endpoint)?;
+            let pair_thread = context.socket(zmq::PAIR).map_err(BenchError::connect)?;
+            pair_thread.bind(INPROC_ENDPOINT).map_err(BenchError::connect)?;
+            let pair = socket(zmq::PAIR, INPROC_ENDPOINT)?;
+
+            let (tx, rx) = mpsc::channel();
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread_stop = stop.clone();
+            std::thread::spawn(move || run_dealer(dealer, pair_thread, tx, thread_stop));
+
+            let send = SocketSender { socket: pair };
+            let recv = ChannelReceiver { rx, stop };
+            return bench.run(send, recv);
+        }
+    }
+}
+
+fn main() {
+    handle_interrupt();
+
+    let config: Config = toml::from_str(
+        &std::fs::read_to_string("config.toml").unwrap()
+    ).unwrap();
+
+    let _supervisor = config.zmq.spawn.as_ref().map(|spawn| {
+        let mut args = vec![config.zmq.pattern.name(), &config.zmq.endpoint];
+        if let Some(endpoint_recv) = &config.zmq.endpoint_recv {
+            args.push(endpoint_recv);
+        }
+
+        let mut supervisor = Supervisor::new(spawn).unwrap();
+        supervisor.spawn_echo("zmq-echo", &args, Readiness::Output("Waiting for messages..")).unwrap();
+        supervisor
+    });
+
+    let schedule = config.zmq.schedule.clone();
+
+    let step = (schedule.stop_req_per_sec - schedule.start_req_per_sec) / schedule.steps as f64;
+    for i in 0..schedule.steps {
+        let duration = Duration::from_secs(schedule.secs_per_step);
+        let num_messages = (schedule.start_req_per_sec * i as f64 + step) * schedule.secs_per_step as f64;
+
+        let result = run_bench(
+            &config.zmq,
+            num_messages.floor() as usize,
+            duration,
+        );
+
+        match result {
+            Ok(stats) => {
+                dbg!(&stats);
+            }
+            Err(e) => {
+                println!("Step {i} failed: {e}");
+                record_failure(&config.zmq.out_file, &e);
+            }
+        }
+
+        if is_interrupted() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Time out regularly to notice the exit request
+const RECV_TIMEOUT_MS: i32 = 100;
+
+fn main() {
+    let args: Vec<String> = std::env::args()
+        .collect();
+
+    let pattern = args.get(1).map(String::as_str).unwrap_or("req_rep");
+    let endpoint = args.get(2).map(String::as_str).unwrap_or("tcp://127.0.0.1:5555");
+    let endpoint_recv = args.get(3).map(String::as_str).unwrap_or("tcp://127.0.0.1:5556");
+
+    let running = Arc::new(AtomicBool::new(true));
+    let exit_running = running.clone();
+    ctrlc::set_handler(move || {
+        exit_running.store(false, Ordering::SeqCst);
+    }).expect("Error setting up exit handler");
+
+    let context = zmq::Context::new();
+    let socket = |kind: zmq::SocketType, endpoint: &str| {
+        let socket = context.socket(kind).unwrap();
+        socket.set_linger(0).unwrap();
+        socket.set_rcvtimeo(RECV_TIMEOUT_MS).unwrap();
+        socket.bind(endpoint).unwrap();
+        return socket;
+    };
+
+    match pattern {
+        "req_rep" => {
+            let rep = socket(zmq::REP, endpoint);
+
+            println!("Waiting for messages..");
+            while running.load(Ordering::SeqCst) {
+                let Ok(msg) = rep.recv_bytes(0) else {
+                    continue;
+                };
+                if let Err(e) = rep.send(msg, 0) {
+                    println!("Send error: {e}");
+                }
+            }
+        }
+        "pub_sub" => {
+            let sub = socket(zmq::SUB, endpoint);
+            sub.set_subscribe(b"").unwrap();
+            let publisher = socket(zmq::PUB, endpoint_recv);
+
+            println!("Waiting for messages..");
+            while running.load(Ordering::SeqCst) {
+                let Ok(msg) = sub.recv_bytes(0) else {
+                    continue;
+                };
+                if let Err(e) = publisher.send(msg, 0) {
+                    println!("Send error: {e}");
+                }
+            }
+        }
+        "dealer_router" => {
+            let router = socket(zmq::ROUTER, endpoint);
+
+            println!("Waiting for messages..");
+            while running.load(Ordering::SeqCst) {
+                // The first frame is the identity of the dealer, which routes the echo back
+                let Ok(frames) = router.recv_multipart(0) else {
+                    continue;
+                };
+                if let Err(e) = router.send_multipart(frames, 0) {
+                    println!("Send error: {e}");
+                }
+            }
+        }
+        _ => panic!("Unknown pattern {pattern}, expected req_rep, pub_sub or dealer_router"),
+    }
+
+    println!("Shutting down");
+}